@@ -0,0 +1,243 @@
+use crate::{Error, Result};
+use std::borrow::{Borrow, ToOwned};
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+use std::fmt;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// a borrowed path which is guaranteed to be absolute.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct AbsolutePath(Path);
+
+/// an owned path which is guaranteed to be absolute.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AbsolutePathBuf(PathBuf);
+
+impl AbsolutePath {
+    /// wrap the path, or return `Error::NotAbsolute` if it's relative.
+    pub fn new(path: &Path) -> Result<&AbsolutePath> {
+        if !path.is_absolute() {
+            return Err(Error::NotAbsolute {
+                path: path.to_path_buf(),
+            });
+        }
+
+        Ok(AbsolutePath::new_unchecked(path))
+    }
+
+    pub(crate) fn new_unchecked(path: &Path) -> &AbsolutePath {
+        debug_assert!(path.is_absolute());
+        // safe because `AbsolutePath` is a `repr(transparent)` wrapper of `Path`.
+        unsafe { &*(path as *const Path as *const AbsolutePath) }
+    }
+
+    pub fn as_path(&self) -> &Path {
+        &self.0
+    }
+
+    /// the parent directory, which is also absolute.
+    pub fn parent(&self) -> Option<&AbsolutePath> {
+        self.0.parent().map(AbsolutePath::new_unchecked)
+    }
+
+    pub fn to_absolute_path_buf(&self) -> AbsolutePathBuf {
+        AbsolutePathBuf(self.0.to_path_buf())
+    }
+}
+
+impl AbsolutePathBuf {
+    /// wrap the path, or return `Error::NotAbsolute` if it's relative.
+    pub fn new(path: PathBuf) -> Result<AbsolutePathBuf> {
+        if !path.is_absolute() {
+            return Err(Error::NotAbsolute { path });
+        }
+
+        Ok(AbsolutePathBuf(path))
+    }
+
+    pub(crate) fn new_unchecked(path: PathBuf) -> AbsolutePathBuf {
+        debug_assert!(path.is_absolute());
+        AbsolutePathBuf(path)
+    }
+
+    pub fn as_absolute_path(&self) -> &AbsolutePath {
+        AbsolutePath::new_unchecked(&self.0)
+    }
+
+    pub fn into_path_buf(self) -> PathBuf {
+        self.0
+    }
+}
+
+impl Deref for AbsolutePath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Deref for AbsolutePathBuf {
+    type Target = AbsolutePath;
+
+    fn deref(&self) -> &AbsolutePath {
+        self.as_absolute_path()
+    }
+}
+
+impl AsRef<Path> for AbsolutePath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<OsStr> for AbsolutePath {
+    fn as_ref(&self) -> &OsStr {
+        self.0.as_os_str()
+    }
+}
+
+impl AsRef<AbsolutePath> for AbsolutePath {
+    fn as_ref(&self) -> &AbsolutePath {
+        self
+    }
+}
+
+impl AsRef<Path> for AbsolutePathBuf {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<OsStr> for AbsolutePathBuf {
+    fn as_ref(&self) -> &OsStr {
+        self.0.as_os_str()
+    }
+}
+
+impl AsRef<AbsolutePath> for AbsolutePathBuf {
+    fn as_ref(&self) -> &AbsolutePath {
+        self.as_absolute_path()
+    }
+}
+
+impl Borrow<AbsolutePath> for AbsolutePathBuf {
+    fn borrow(&self) -> &AbsolutePath {
+        self.as_absolute_path()
+    }
+}
+
+impl ToOwned for AbsolutePath {
+    type Owned = AbsolutePathBuf;
+
+    fn to_owned(&self) -> AbsolutePathBuf {
+        self.to_absolute_path_buf()
+    }
+}
+
+impl<'a> TryFrom<&'a Path> for &'a AbsolutePath {
+    type Error = Error;
+
+    fn try_from(path: &'a Path) -> Result<&'a AbsolutePath> {
+        AbsolutePath::new(path)
+    }
+}
+
+impl TryFrom<PathBuf> for AbsolutePathBuf {
+    type Error = Error;
+
+    fn try_from(path: PathBuf) -> Result<AbsolutePathBuf> {
+        AbsolutePathBuf::new(path)
+    }
+}
+
+impl<'a> TryFrom<&'a Path> for AbsolutePathBuf {
+    type Error = Error;
+
+    fn try_from(path: &'a Path) -> Result<AbsolutePathBuf> {
+        AbsolutePathBuf::new(path.to_path_buf())
+    }
+}
+
+impl<'a> From<&'a AbsolutePath> for AbsolutePathBuf {
+    fn from(path: &'a AbsolutePath) -> AbsolutePathBuf {
+        path.to_absolute_path_buf()
+    }
+}
+
+impl From<AbsolutePathBuf> for PathBuf {
+    fn from(path: AbsolutePathBuf) -> PathBuf {
+        path.0
+    }
+}
+
+impl fmt::Display for AbsolutePath {
+    fn fmt(&self, b: &mut fmt::Formatter) -> fmt::Result {
+        write!(b, "{}", self.0.display())
+    }
+}
+
+impl fmt::Display for AbsolutePathBuf {
+    fn fmt(&self, b: &mut fmt::Formatter) -> fmt::Result {
+        write!(b, "{}", self.0.display())
+    }
+}
+
+macro_rules! impl_partial_eq {
+    ($lhs:ty, $rhs:ty) => {
+        impl<'a> PartialEq<$rhs> for $lhs {
+            fn eq(&self, other: &$rhs) -> bool {
+                <Path as PartialEq>::eq(self.as_ref(), other.as_ref())
+            }
+        }
+
+        impl<'a> PartialEq<$lhs> for $rhs {
+            fn eq(&self, other: &$lhs) -> bool {
+                <Path as PartialEq>::eq(self.as_ref(), other.as_ref())
+            }
+        }
+    };
+}
+
+impl_partial_eq!(AbsolutePathBuf, AbsolutePath);
+impl_partial_eq!(AbsolutePathBuf, &'a AbsolutePath);
+impl_partial_eq!(AbsolutePathBuf, Path);
+impl_partial_eq!(AbsolutePathBuf, &'a Path);
+impl_partial_eq!(AbsolutePathBuf, PathBuf);
+impl_partial_eq!(AbsolutePath, Path);
+impl_partial_eq!(AbsolutePath, PathBuf);
+impl_partial_eq!(&'a AbsolutePath, PathBuf);
+
+#[cfg(test)]
+mod tests {
+    use super::{AbsolutePath, AbsolutePathBuf};
+    use crate::Error;
+    use std::collections::HashSet;
+    use std::convert::TryFrom;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_absolute_path() {
+        let root = if cfg!(windows) { r#"C:\"# } else { "/" };
+        let absolute = PathBuf::from(root).join("dir").join("file");
+
+        let owned = AbsolutePathBuf::try_from(absolute.clone()).unwrap();
+        let borrowed = <&AbsolutePath>::try_from(absolute.as_path()).unwrap();
+        assert_eq!(owned, borrowed);
+        assert_eq!(owned, absolute);
+        assert_eq!(absolute.display().to_string(), owned.to_string());
+        assert_eq!(Path::new(root).join("dir"), owned.parent().unwrap());
+        assert!(owned.ends_with("file"));
+
+        let mut set = HashSet::new();
+        set.insert(owned.clone());
+        assert!(set.contains(borrowed));
+
+        match AbsolutePathBuf::try_from(PathBuf::from("relative")) {
+            Err(Error::NotAbsolute { ref path }) => assert_eq!(Path::new("relative"), path),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
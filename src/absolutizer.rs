@@ -0,0 +1,762 @@
+use crate::tilde;
+use crate::vars::{self, VarExpansion};
+use crate::walk::{Limits, Walk};
+use crate::{
+    normalize_lexical, Error, FileKind, Fs, HomeDirs, Operation, Result, StdFs, SystemHomeDirs,
+};
+use crate::{AbsolutePathBuf, BeneathBackend, CanonicalPathBuf, PrefixKind, WindowsPrefix};
+use crate::{VarSource, VarSyntax};
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::{self, Component, Path, PathBuf};
+use std::result;
+use std::sync::Arc;
+
+/// how to treat Windows path prefixes that can't be simplified (e.g. `\\?\`
+/// followed by something other than a drive). Has no effect on other
+/// platforms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PrefixPolicy {
+    /// return `Error::UnsupportedPrefix`.
+    Strict,
+    /// leave the prefix as is.
+    Preserve,
+}
+
+/// the form of the resolved path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OutputForm {
+    /// remove unusual prefix (e.g. `\\?\C:\` becomes `C:\`).
+    Plain,
+    /// keep the path as the OS returned it.
+    Native,
+}
+
+/// which symlinks to follow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymlinkPolicy {
+    /// follow every symlink, like `std::fs::canonicalize`.
+    FollowAll,
+    /// follow symlinks in the parent directories but not the last component,
+    /// so the result is the path of the link itself. A path ending with a
+    /// separator or `.` (e.g. `link/`) names the target, so it's followed.
+    FollowIntermediate,
+    /// follow no symlinks. `.` and `..` are resolved lexically.
+    FollowNone,
+}
+
+/// configurable absolutization.
+///
+/// The default configuration is the same as `canonicalize`: symlinks are
+/// followed, the file must exist and the result is in the plain form.
+#[derive(Debug, Clone)]
+pub struct Absolutizer {
+    base: Option<PathBuf>,
+    symlink_policy: SymlinkPolicy,
+    must_exist: bool,
+    prefix_policy: PrefixPolicy,
+    output_form: OutputForm,
+    fs: Arc<dyn Fs + Send + Sync>,
+    beneath_backend: BeneathBackend,
+    max_symlinks: Option<usize>,
+    max_depth: Option<usize>,
+    home_dirs: Option<Arc<dyn HomeDirs + Send + Sync>>,
+    vars: Option<VarExpansion>,
+}
+
+impl Default for Absolutizer {
+    fn default() -> Absolutizer {
+        Absolutizer::new()
+    }
+}
+
+impl Absolutizer {
+    pub fn new() -> Absolutizer {
+        Absolutizer {
+            base: None,
+            symlink_policy: SymlinkPolicy::FollowAll,
+            must_exist: true,
+            prefix_policy: PrefixPolicy::Strict,
+            output_form: OutputForm::Plain,
+            fs: Arc::new(StdFs),
+            beneath_backend: BeneathBackend::Fs,
+            max_symlinks: None,
+            max_depth: None,
+            home_dirs: None,
+            vars: None,
+        }
+    }
+
+    /// the directory relative paths are resolved against. It must be an
+    /// absolute path. Defaults to the current working directory at the time of
+    /// `resolve`.
+    pub fn base(mut self, base: impl Into<PathBuf>) -> Absolutizer {
+        self.base = Some(base.into());
+        self
+    }
+
+    /// whether to resolve symlinks. If not, `.` and `..` are resolved
+    /// lexically. Same as `SymlinkPolicy::FollowAll` or
+    /// `SymlinkPolicy::FollowNone`.
+    pub fn follow_symlinks(self, follow_symlinks: bool) -> Absolutizer {
+        self.symlink_policy(if follow_symlinks {
+            SymlinkPolicy::FollowAll
+        } else {
+            SymlinkPolicy::FollowNone
+        })
+    }
+
+    pub fn symlink_policy(mut self, symlink_policy: SymlinkPolicy) -> Absolutizer {
+        self.symlink_policy = symlink_policy;
+        self
+    }
+
+    /// whether the file must exist. If not, the longest existing prefix is
+    /// resolved and the rest is appended lexically. A dangling symlink in the
+    /// path is still followed to its target.
+    pub fn must_exist(mut self, must_exist: bool) -> Absolutizer {
+        self.must_exist = must_exist;
+        self
+    }
+
+    pub fn prefix_policy(mut self, prefix_policy: PrefixPolicy) -> Absolutizer {
+        self.prefix_policy = prefix_policy;
+        self
+    }
+
+    pub fn output_form(mut self, output_form: OutputForm) -> Absolutizer {
+        self.output_form = output_form;
+        self
+    }
+
+    /// the filesystem to resolve paths in. Defaults to `StdFs`.
+    pub fn fs<F: Fs + Send + Sync + 'static>(mut self, fs: F) -> Absolutizer {
+        self.fs = Arc::new(fs);
+        self
+    }
+
+    /// how `resolve_beneath` walks the path. Defaults to
+    /// `BeneathBackend::Fs`.
+    pub fn beneath_backend(mut self, beneath_backend: BeneathBackend) -> Absolutizer {
+        self.beneath_backend = beneath_backend;
+        self
+    }
+
+    /// the maximum number of symlinks followed in one resolution. Defaults to
+    /// 40. Exceeding it returns `Error::TooManySymlinks`.
+    ///
+    /// If this or `max_depth` is set, symlinks are resolved with the crate's own
+    /// component walk instead of `Fs::canonicalize`.
+    pub fn max_symlinks(mut self, max_symlinks: usize) -> Absolutizer {
+        self.max_symlinks = Some(max_symlinks);
+        self
+    }
+
+    /// the maximum number of components of the resolved path, including
+    /// the root. Exceeding it returns `Error::TooDeep`. Unlimited by default.
+    pub fn max_depth(mut self, max_depth: usize) -> Absolutizer {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// whether to replace the leading `~` or `~user` of relative paths with
+    /// the home directory before joining. Off by default.
+    pub fn expand_tilde(mut self, expand_tilde: bool) -> Absolutizer {
+        self.home_dirs = if expand_tilde {
+            Some(Arc::new(SystemHomeDirs))
+        } else {
+            None
+        };
+        self
+    }
+
+    /// expand `~` and `~user` with the home directories from `home_dirs`.
+    pub fn home_dirs(mut self, home_dirs: impl HomeDirs + Send + Sync + 'static) -> Absolutizer {
+        self.home_dirs = Some(Arc::new(home_dirs));
+        self
+    }
+
+    /// replace variable references in the path with the values from `source`
+    /// before joining (after expanding the tilde). Off by default.
+    pub fn expand_vars(
+        mut self,
+        syntax: VarSyntax,
+        source: impl VarSource + Send + Sync + 'static,
+    ) -> Absolutizer {
+        self.vars = Some(VarExpansion {
+            syntax,
+            source: Arc::new(source),
+        });
+        self
+    }
+
+    /// get the absolute path for specified path with this configuration.
+    pub fn resolve(&self, path: impl AsRef<Path>) -> Result<AbsolutePathBuf> {
+        let joined = self.joined(path.as_ref())?;
+        let resolved = self.resolve_joined(&joined)?;
+        self.finish(resolved)
+    }
+
+    /// canonicalize the path with this configuration. Symlinks are always
+    /// followed, the file must exist and the result is in the plain form,
+    /// regardless of `symlink_policy`, `must_exist` and `output_form`.
+    pub fn canonicalize(&self, path: impl AsRef<Path>) -> Result<CanonicalPathBuf> {
+        let joined = self.joined(path.as_ref())?;
+        self.canonicalize_joined(&joined)
+    }
+
+    /// canonicalize the path which is already joined to the base directory.
+    pub(crate) fn canonicalize_joined(&self, joined: &Path) -> Result<CanonicalPathBuf> {
+        let canonicalizer = self.canonicalizer();
+        let resolved = canonicalizer.resolve_joined(joined)?;
+        Ok(CanonicalPathBuf::new_unchecked(
+            canonicalizer.finish(resolved)?,
+        ))
+    }
+
+    /// this configuration with the settings of `canonicalize`. The output
+    /// form is fixed, so canonical paths of the same file compare equal.
+    pub(crate) fn canonicalizer(&self) -> Absolutizer {
+        self.clone()
+            .follow_symlinks(true)
+            .must_exist(true)
+            .output_form(OutputForm::Plain)
+    }
+
+    pub(crate) fn is_canonicalizing(&self) -> bool {
+        self.symlink_policy == SymlinkPolicy::FollowAll && self.must_exist
+    }
+
+    pub(crate) fn filesystem(&self) -> &(dyn Fs + Send + Sync) {
+        &*self.fs
+    }
+
+    /// whether the component walk gives the same result as
+    /// `Fs::canonicalize` for the joined path, so a cached walk can be used.
+    pub(crate) fn walk_agrees(&self, joined: &Path) -> bool {
+        // the OS rejects `..` and a trailing separator after a file, which the
+        // walk can't see.
+        self.fs.canonicalizes_by_walk()
+            && !ends_with_separator_or_dot(joined)
+            && !joined.components().any(|c| c == Component::ParentDir)
+    }
+
+    pub(crate) fn backend(&self) -> BeneathBackend {
+        self.beneath_backend
+    }
+
+    pub(crate) fn requires_existing(&self) -> bool {
+        self.must_exist
+    }
+
+    /// the component walk with the configured limits.
+    pub(crate) fn walk(&self) -> Walk<'_, dyn Fs + Send + Sync> {
+        Walk::new(&*self.fs).limits(self.limits())
+    }
+
+    pub(crate) fn limits(&self) -> Limits {
+        let default = Limits::default();
+        Limits {
+            max_symlinks: self.max_symlinks.unwrap_or(default.max_symlinks),
+            max_depth: self.max_depth.or(default.max_depth),
+        }
+    }
+
+    pub(crate) fn shared_fs(&self) -> Arc<dyn Fs + Send + Sync> {
+        Arc::clone(&self.fs)
+    }
+
+    /// the base directory as given, without falling back to the current
+    /// directory.
+    pub(crate) fn explicit_base(&self) -> Option<&Path> {
+        self.base.as_deref()
+    }
+
+    /// this configuration resolving relative paths against the current
+    /// directory.
+    pub(crate) fn without_base(&self) -> Absolutizer {
+        Absolutizer {
+            base: None,
+            ..self.clone()
+        }
+    }
+
+    /// expand the tilde and the variables if enabled, and join the path to
+    /// the base directory.
+    pub(crate) fn joined(&self, path: &Path) -> Result<PathBuf> {
+        let mut path = path.to_path_buf();
+        if let Some(ref home_dirs) = self.home_dirs {
+            path = tilde::expand_with(&path, &**home_dirs)?;
+        }
+        if let Some(ref expansion) = self.vars {
+            path = vars::expand_vars(&path, expansion.syntax, &*expansion.source)?;
+        }
+        if path.is_absolute() {
+            return Ok(path);
+        }
+
+        Ok(self.base_dir()?.join(path))
+    }
+
+    /// whether paths are rewritten before joining.
+    pub(crate) fn expands(&self) -> bool {
+        self.home_dirs.is_some() || self.vars.is_some()
+    }
+
+    /// the absolute directory relative paths are resolved against.
+    pub(crate) fn base_dir(&self) -> Result<PathBuf> {
+        let base = match self.base {
+            Some(ref base) => base.clone(),
+            None => self
+                .fs
+                .current_dir()
+                .map_err(|e| Error::io(Operation::CurrentDir, "", e))?,
+        };
+        if !base.is_absolute() {
+            return Err(Error::CurrentIsRelative { current: base });
+        }
+
+        Ok(base)
+    }
+
+    /// resolve the path which is already joined to the base directory.
+    pub(crate) fn resolve_joined(&self, joined: &Path) -> Result<PathBuf> {
+        let resolved = match self.symlink_policy {
+            SymlinkPolicy::FollowAll => self.follow_all(joined)?,
+            // a trailing separator or `.` names the directory a symlink points
+            // to, so the last component has to be followed too.
+            SymlinkPolicy::FollowIntermediate if ends_with_separator_or_dot(joined) => {
+                self.follow_all(joined)?
+            }
+            SymlinkPolicy::FollowIntermediate => match (joined.parent(), joined.file_name()) {
+                (Some(parent), Some(name)) => self.exists(self.follow_all(parent)?.join(name))?,
+                // the last component is `..` (or the path is a root), so it
+                // has to be followed to find the parent.
+                _ => self.follow_all(joined)?,
+            },
+            SymlinkPolicy::FollowNone => self.exists(normalize_lexical(joined))?,
+        };
+        // the walk only checks the components it visits, so check the final
+        // path too (e.g. its missing tail, or a path resolved lexically).
+        self.check_depth(&resolved)?;
+
+        Ok(resolved)
+    }
+
+    pub(crate) fn check_depth(&self, resolved: &Path) -> Result<()> {
+        match self.max_depth {
+            Some(max) if resolved.components().count() > max => Err(Error::TooDeep {
+                path: resolved.to_path_buf(),
+                max,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// check that the path exists if it must.
+    fn exists(&self, path: PathBuf) -> Result<PathBuf> {
+        if self.must_exist {
+            self.fs
+                .symlink_metadata(&path)
+                .map_err(|e| Error::io(Operation::Metadata, &path, e))?;
+        }
+
+        Ok(path)
+    }
+
+    fn follow_all(&self, path: &Path) -> Result<PathBuf> {
+        if self.must_exist {
+            self.canonicalize_existing(path)
+        } else {
+            self.canonicalize_partial(path, 0)
+        }
+    }
+
+    /// canonicalize the absolute path which may not exist yet. A dangling
+    /// symlink is followed to its target, like `realpath -m`. `expansions` is
+    /// the number of dangling symlinks followed so far.
+    fn canonicalize_partial(&self, path: &Path, expansions: usize) -> Result<PathBuf> {
+        // `resolved` is the canonicalized existing part, and `missing` is the
+        // non-existent tail. Once a component is missing, all the following
+        // components are missing too until `..` pops them all.
+        let mut resolved = PathBuf::new();
+        let mut missing = PathBuf::new();
+        let mut components = path.components();
+        while let Some(component) = components.next() {
+            match component {
+                Component::Prefix(_) | Component::RootDir => resolved.push(component),
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if !missing.pop() {
+                        resolved.pop();
+                    }
+                }
+                Component::Normal(name) if missing.as_os_str().is_empty() => {
+                    let next = resolved.join(name);
+                    match self.canonicalize_existing(&next) {
+                        Ok(canonicalized) => resolved = canonicalized,
+                        Err(ref e) if e.io_error_kind() == Some(io::ErrorKind::NotFound) => {
+                            if let Some(target) = self.dangling_target(&next, expansions)? {
+                                // the relative target is relative to `resolved`.
+                                let retry = resolved.join(target).join(components.as_path());
+                                return self.canonicalize_partial(&retry, expansions + 1);
+                            }
+                            missing.push(name)
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                Component::Normal(name) => missing.push(name),
+            }
+        }
+
+        Ok(resolved.join(missing))
+    }
+
+    /// the target of `path` if it's a symlink, which is dangling since
+    /// canonicalizing it failed.
+    fn dangling_target(&self, path: &Path, expansions: usize) -> Result<Option<PathBuf>> {
+        match self.fs.symlink_metadata(path) {
+            Ok(FileKind::Symlink) => {}
+            _ => return Ok(None),
+        }
+        let max = self.limits().max_symlinks;
+        if expansions >= max {
+            return Err(Error::TooManySymlinks {
+                path: path.to_path_buf(),
+                max,
+            });
+        }
+
+        self.fs
+            .read_link(path)
+            .map(Some)
+            .map_err(|e| Error::io(Operation::ReadLink, path, e))
+    }
+
+    /// canonicalize the existing absolute path.
+    pub(crate) fn canonicalize_existing(&self, path: &Path) -> Result<PathBuf> {
+        if self.max_symlinks.is_some() || self.max_depth.is_some() {
+            return self.walk().canonicalize(path);
+        }
+
+        let canonicalized = self.fs.canonicalize(path).or_else(|e| {
+            if is_symlink_loop(&e) {
+                // walk again to find the members of the loop.
+                self.walk().canonicalize(path).and(Err(e))
+            } else {
+                Err(e)
+            }
+        })?;
+        // `Fs::canonicalize` may be overridden, so don't trust it blindly.
+        if !canonicalized.is_absolute() {
+            return Err(Error::NotAbsolute {
+                path: canonicalized,
+            });
+        }
+
+        Ok(canonicalized)
+    }
+
+    /// convert the resolved path to the configured output form.
+    pub(crate) fn finish(&self, resolved: PathBuf) -> Result<AbsolutePathBuf> {
+        let resolved = match self.output_form {
+            OutputForm::Plain => simplify_prefix(&resolved, self.prefix_policy)?,
+            OutputForm::Native => resolved,
+        };
+
+        Ok(AbsolutePathBuf::new_unchecked(resolved))
+    }
+}
+
+/// whether the path as written ends with a separator or `.`, which
+/// `Path::components` drops.
+pub(crate) fn ends_with_separator_or_dot(path: &Path) -> bool {
+    let text = path.as_os_str().to_string_lossy();
+    let mut chars = text.chars().rev();
+    match chars.next() {
+        Some(c) if path::is_separator(c) => true,
+        Some('.') => match chars.next() {
+            Some(c) => path::is_separator(c),
+            None => true,
+        },
+        _ => false,
+    }
+}
+
+/// whether the OS reported a symlink loop (`ELOOP`).
+fn is_symlink_loop(error: &Error) -> bool {
+    match *error {
+        #[cfg(unix)]
+        Error::Io { ref source, .. } => source.raw_os_error() == Some(libc::ELOOP),
+        _ => false,
+    }
+}
+
+/// remove unusual prefix (e.g. `\\?\`) from the path.
+fn simplify_prefix(path: &Path, policy: PrefixPolicy) -> Result<PathBuf> {
+    let components = path.components().map(|component| match component {
+        Component::Prefix(prefix) => match (simplified_prefix(prefix.as_os_str()), policy) {
+            (Ok(simplified), _) => Ok(simplified),
+            (Err(kind), PrefixPolicy::Strict) => Err(Error::UnsupportedPrefix {
+                path: path.to_path_buf(),
+                prefix: kind,
+            }),
+            (Err(_), PrefixPolicy::Preserve) => Ok(prefix.as_os_str().to_os_string()),
+        },
+        other => Ok(other.as_os_str().to_os_string()),
+    });
+
+    components.collect()
+}
+
+/// get the plain form of the prefix, or the kind of the prefix if it has no
+/// plain form.
+fn simplified_prefix(prefix: &OsStr) -> result::Result<OsString, PrefixKind> {
+    let lossy = prefix.to_string_lossy();
+    let parsed = match WindowsPrefix::parse(&lossy) {
+        Some((parsed, _)) => parsed,
+        None => return Ok(prefix.to_os_string()),
+    };
+    match (prefix.to_str(), parsed.plain()) {
+        // the plain form built from a lossy string would be a different path.
+        (Some(_), Some(plain)) => Ok(plain.to_string().into()),
+        _ => Err(parsed.kind()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{simplified_prefix, Absolutizer, SymlinkPolicy};
+    use crate::testing::FakeFs;
+    use crate::{Error, Operation, PrefixKind};
+    use std::env;
+    use std::ffi::OsStr;
+    use std::io;
+    use std::path::Path;
+
+    #[test]
+    fn test_options() {
+        let absolutizer = Absolutizer::new()
+            .fs(FakeFs::new().dir("/temp/dir"))
+            .base("/temp");
+        assert_eq!(
+            Path::new("/temp/dir"),
+            absolutizer.resolve("dir/../dir").unwrap()
+        );
+        assert!(absolutizer.resolve("missing").is_err());
+
+        let lexical = absolutizer.clone().follow_symlinks(false);
+        assert_eq!(
+            Path::new("/temp/dir"),
+            lexical.resolve("missing/../dir").unwrap()
+        );
+        assert!(lexical.resolve("dir/missing").is_err());
+        assert_eq!(
+            Path::new("/temp/dir/missing"),
+            lexical.must_exist(false).resolve("dir/missing").unwrap()
+        );
+
+        match Absolutizer::new().base("relative").resolve("dir") {
+            Err(Error::CurrentIsRelative { ref current }) => {
+                assert_eq!(Path::new("relative"), current)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_symlink_policy() {
+        let fs = FakeFs::new()
+            .file("/data/real/file")
+            .symlink("real", "/data/link")
+            .symlink("real/file", "/data/file-link")
+            .symlink("/nowhere", "/data/dangling")
+            .dir("/b")
+            .symlink("/b/target", "/a/dl")
+            .symlink("../b/target", "/a/relative-dl")
+            .symlink("dl", "/a/chained-dl");
+        let resolve = |policy: SymlinkPolicy, path: &str| {
+            Absolutizer::new()
+                .fs(fs.clone())
+                .symlink_policy(policy)
+                .resolve(path)
+        };
+
+        let all = |path: &str| resolve(SymlinkPolicy::FollowAll, path);
+        assert_eq!(
+            Path::new("/data/real/file"),
+            all("/data/file-link").unwrap()
+        );
+        assert_eq!(
+            Path::new("/data/real/file"),
+            all("/data/link/file").unwrap()
+        );
+        assert!(all("/data/dangling").is_err());
+
+        let partial = |path: &str| {
+            Absolutizer::new()
+                .fs(fs.clone())
+                .must_exist(false)
+                .resolve(path)
+        };
+        assert_eq!(Path::new("/b/target/new"), partial("/a/dl/new").unwrap());
+        assert_eq!(Path::new("/b/target"), partial("/a/dl").unwrap());
+        assert_eq!(
+            Path::new("/b/target/new"),
+            partial("/a/relative-dl/new").unwrap()
+        );
+        assert_eq!(
+            Path::new("/b/target/new"),
+            partial("/a/chained-dl/./x/../new").unwrap()
+        );
+        assert_eq!(Path::new("/b"), partial("/a/dl/..").unwrap());
+
+        let intermediate = |path: &str| resolve(SymlinkPolicy::FollowIntermediate, path);
+        assert_eq!(
+            Path::new("/data/file-link"),
+            intermediate("/data/file-link").unwrap()
+        );
+        assert_eq!(
+            Path::new("/data/real/file"),
+            intermediate("/data/link/file").unwrap()
+        );
+        assert_eq!(
+            Path::new("/data/file-link"),
+            intermediate("/data/link/../file-link").unwrap()
+        );
+        assert_eq!(Path::new("/data"), intermediate("/data/link/..").unwrap());
+        assert_eq!(
+            Path::new("/data/real"),
+            intermediate("/data/link/").unwrap()
+        );
+        assert_eq!(
+            Path::new("/data/real"),
+            intermediate("/data/link/.").unwrap()
+        );
+        assert_eq!(Path::new("/data/link"), intermediate("/data/link").unwrap());
+        assert_eq!(
+            Path::new("/data/dangling"),
+            intermediate("/data/dangling").unwrap()
+        );
+        assert!(intermediate("/data/link/missing").is_err());
+
+        let none = |path: &str| resolve(SymlinkPolicy::FollowNone, path);
+        assert_eq!(Path::new("/data/link"), none("/data/link").unwrap());
+        assert_eq!(
+            Path::new("/data/file-link"),
+            none("/data/link/../file-link").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let fs = FakeFs::new()
+            .symlink("b", "/l/a")
+            .symlink("c", "/l/b")
+            .symlink("/l/a", "/l/c")
+            .symlink("y", "/l/x")
+            .symlink("z", "/l/y")
+            .dir("/l/z/1/2/3");
+        let absolutizer = Absolutizer::new().fs(fs);
+
+        match absolutizer.resolve("/l/a/file") {
+            Err(Error::SymlinkLoop { path, members }) => {
+                assert_eq!(Path::new("/l/a"), path);
+                assert_eq!(
+                    vec![Path::new("/l/a"), Path::new("/l/b"), Path::new("/l/c")],
+                    members
+                );
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(absolutizer.clone().max_symlinks(2).resolve("/l/x").is_ok());
+        match absolutizer.clone().max_symlinks(1).resolve("/l/x") {
+            Err(Error::TooManySymlinks { path, max: 1 }) => assert_eq!(Path::new("/l/y"), path),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let shallow = absolutizer.clone().max_depth(4);
+        assert!(shallow.resolve("/l/z/1").is_ok());
+        match shallow.resolve("/l/z/1/2/3") {
+            Err(Error::TooDeep { path, max: 4 }) => assert_eq!(Path::new("/l/z/1/2"), path),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(shallow
+            .clone()
+            .must_exist(false)
+            .resolve("/l/z/1/2/new")
+            .is_err());
+        // the missing tail and paths resolved without the walk count too.
+        match shallow.clone().must_exist(false).resolve("/l/z/1/new/a") {
+            Err(Error::TooDeep { path, max: 4 }) => assert_eq!(Path::new("/l/z/1/new/a"), path),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(shallow
+            .clone()
+            .follow_symlinks(false)
+            .resolve("/l/z/1/2")
+            .is_err());
+        assert!(shallow
+            .clone()
+            .symlink_policy(SymlinkPolicy::FollowIntermediate)
+            .resolve("/l/z/1/2")
+            .is_err());
+        assert!(shallow
+            .symlink_policy(SymlinkPolicy::FollowIntermediate)
+            .resolve("/l/z/1")
+            .is_ok());
+
+        // the OS reports the loop itself.
+        #[cfg(unix)]
+        {
+            let temp = crate::testing::TempDir::new("loop");
+            std::os::unix::fs::symlink("loop", temp.join("loop")).unwrap();
+
+            match Absolutizer::new().resolve(temp.join("loop")) {
+                Err(Error::SymlinkLoop { members, .. }) => {
+                    assert_eq!(vec![temp.join("loop")], members)
+                }
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_error_context() {
+        use std::error::Error as _;
+
+        let missing = env::temp_dir().join("to_absolute-missing").join("file");
+        let error = Absolutizer::new().resolve(&missing).unwrap_err();
+        assert_eq!(missing, error.path());
+        assert_eq!(Some(Operation::Canonicalize), error.operation());
+        assert_eq!(Some(io::ErrorKind::NotFound), error.io_error_kind());
+        assert!(error.source().is_some());
+    }
+
+    #[test]
+    fn test_simplified_prefix() {
+        let simplified = |prefix: &str| {
+            simplified_prefix(OsStr::new(prefix))
+                .ok()
+                .map(|s| s.into_string().unwrap())
+        };
+
+        assert_eq!(Some("C:".to_string()), simplified(r#"C:"#));
+        assert_eq!(Some("C:".to_string()), simplified(r#"\\?\C:"#));
+        assert_eq!(
+            Some(r#"\\server\share"#.to_string()),
+            simplified(r#"\\server\share"#)
+        );
+        assert_eq!(
+            Some(r#"\\server\share"#.to_string()),
+            simplified(r#"\\?\UNC\server\share"#)
+        );
+        assert_eq!(None, simplified(r#"\\?\pictures"#));
+        assert_eq!(None, simplified(r#"\\.\COM1"#));
+        assert_eq!(
+            Err(PrefixKind::Verbatim),
+            simplified_prefix(OsStr::new(r#"\\?\pictures"#))
+        );
+    }
+}
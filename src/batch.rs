@@ -0,0 +1,216 @@
+use crate::walk::{Limits, Walk, WalkCache};
+use crate::{AbsolutePathBuf, Absolutizer, Result};
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+impl Absolutizer {
+    /// resolve many paths at once. The result is the same as calling `resolve`
+    /// for each path, but the base directory is read only once and, when
+    /// canonicalizing with a filesystem whose `Fs::canonicalizes_by_walk` is
+    /// true, each directory prefix shared by the paths is resolved only once.
+    pub fn resolve_all<I>(&self, paths: I) -> Vec<Result<AbsolutePathBuf>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+    {
+        let paths = paths
+            .into_iter()
+            .map(|p| p.as_ref().to_path_buf())
+            .collect();
+        self.resolve_batch(paths, false)
+    }
+
+    /// same as `resolve_all`, but runs on the rayon thread pool.
+    #[cfg(feature = "rayon")]
+    pub fn par_resolve_all<I>(&self, paths: I) -> Vec<Result<AbsolutePathBuf>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+    {
+        let paths = paths
+            .into_iter()
+            .map(|p| p.as_ref().to_path_buf())
+            .collect();
+        self.resolve_batch(paths, true)
+    }
+
+    fn resolve_batch(&self, paths: Vec<PathBuf>, parallel: bool) -> Vec<Result<AbsolutePathBuf>> {
+        // the limits count per resolution, which a shared cache would skip,
+        // and expanded paths aren't joined to the base, so the shortcut can't
+        // be used.
+        if !self.is_canonicalizing() || self.limits() != Limits::default() || self.expands() {
+            return map_items(&paths, parallel, |path| self.resolve(path));
+        }
+
+        let joined: Vec<PathBuf> = if paths.iter().all(|path| path.is_absolute()) {
+            paths
+        } else {
+            match self.base_dir() {
+                Ok(base) => paths.iter().map(|path| base.join(path)).collect(),
+                // let each path report its own error.
+                Err(_) => return map_items(&paths, parallel, |path| self.resolve(path)),
+            }
+        };
+
+        let cache = WalkCache::default();
+        map_items(&joined, parallel, |path| {
+            if !self.walk_agrees(path) {
+                return self.resolve(path);
+            }
+            match Walk::with_cache(self.filesystem(), &cache).canonicalize(path) {
+                Ok(resolved) => self.finish(resolved),
+                // errors are rare, so let `resolve` report them with this path.
+                Err(_) => self.resolve(path),
+            }
+        })
+    }
+}
+
+fn map_items<T, U, F>(items: &[T], parallel: bool, f: F) -> Vec<U>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&T) -> U + Sync + Send,
+{
+    #[cfg(feature = "rayon")]
+    {
+        if parallel {
+            return items.par_iter().map(f).collect();
+        }
+    }
+    #[cfg(not(feature = "rayon"))]
+    let _ = parallel;
+
+    items.iter().map(f).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::FakeFs;
+    use crate::{Absolutizer, FileKind, Fs, Result};
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_resolve_all() {
+        let fs = FakeFs::new()
+            .file("/temp/a/x")
+            .file("/temp/a/y")
+            .dir("/temp/b")
+            .symlink("/temp/a/x", "/temp/b/link");
+
+        let paths = [
+            "a/x",
+            "a/y",
+            "a/../a/x",
+            "b/link",
+            "b/missing",
+            "missing/x",
+            "a/..",
+            ".",
+        ];
+        let absolutizer = Absolutizer::new().fs(fs).base("/temp");
+        let expected: Vec<_> = paths
+            .iter()
+            .map(|path| absolutizer.resolve(path).map_err(|e| e.to_string()))
+            .collect();
+
+        let resolve_all = |absolutizer: &Absolutizer| -> Vec<_> {
+            absolutizer
+                .resolve_all(&paths)
+                .into_iter()
+                .map(|result| result.map_err(|e| e.to_string()))
+                .collect()
+        };
+        assert_eq!(expected, resolve_all(&absolutizer));
+        assert!(expected[0].is_ok() && expected[4].is_err());
+
+        let lexical = absolutizer.clone().follow_symlinks(false);
+        let expected: Vec<_> = paths
+            .iter()
+            .map(|path| lexical.resolve(path).map_err(|e| e.to_string()))
+            .collect();
+        assert_eq!(expected, resolve_all(&lexical));
+
+        #[cfg(feature = "rayon")]
+        {
+            let parallel: Vec<_> = absolutizer
+                .par_resolve_all(&paths)
+                .into_iter()
+                .map(|result| result.map_err(|e| e.to_string()))
+                .collect();
+            assert_eq!(resolve_all(&absolutizer), parallel);
+        }
+    }
+
+    #[test]
+    fn test_shared_prefixes() {
+        let fs = FakeFs::new()
+            .file("/srv/data/a/x")
+            .file("/srv/data/a/y")
+            .file("/srv/data/b/z")
+            .symlink("data/a", "/srv/link");
+        let absolutizer = Absolutizer::new().fs(fs.clone());
+        let paths = [
+            "/srv/data/a/x",
+            "/srv/data/a/y",
+            "/srv/data/b/z",
+            "/srv/link/y",
+        ];
+
+        for path in &paths {
+            absolutizer.resolve(path).unwrap();
+        }
+        let separately = fs.calls();
+        fs.reset_calls();
+
+        let resolved: Vec<_> = absolutizer
+            .resolve_all(&paths)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(Path::new("/srv/data/a/y"), resolved[3]);
+        // `srv`, `data`, `a` and `x`, then `y`, then `b` and `z`, then reading
+        // `link`, whose target is already resolved.
+        assert_eq!(9, fs.calls());
+        assert!(fs.calls() < separately);
+    }
+
+    #[test]
+    fn test_canonicalize_override() {
+        /// moves every canonicalized file to `/elsewhere`.
+        #[derive(Debug)]
+        struct MovingFs(FakeFs);
+
+        impl Fs for MovingFs {
+            fn symlink_metadata(&self, path: &Path) -> io::Result<FileKind> {
+                self.0.symlink_metadata(path)
+            }
+
+            fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+                self.0.read_link(path)
+            }
+
+            fn current_dir(&self) -> io::Result<PathBuf> {
+                self.0.current_dir()
+            }
+
+            fn canonicalize(&self, path: &Path) -> Result<PathBuf> {
+                let name = path.file_name().unwrap_or_default();
+                Ok(Path::new("/elsewhere").join(name))
+            }
+        }
+
+        let fs = MovingFs(FakeFs::new().file("/temp/a/x").file("/temp/a/y"));
+        let absolutizer = Absolutizer::new().fs(fs).base("/temp");
+        let resolved: Vec<_> = absolutizer
+            .resolve_all(["a/x", "/temp/a/y"])
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(Path::new("/elsewhere/x"), resolved[0]);
+        assert_eq!(Path::new("/elsewhere/y"), resolved[1]);
+    }
+}
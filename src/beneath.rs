@@ -0,0 +1,280 @@
+use crate::{AbsolutePathBuf, Absolutizer, Error, FileKind, Fs, Operation, Result};
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// how `Absolutizer::resolve_beneath` walks the path. Both backends reject
+/// the same paths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BeneathBackend {
+    /// walk the path one component at a time with the configured `Fs`.
+    Fs,
+    /// let the kernel resolve the path on the real filesystem in one step
+    /// (see the `linux` module), so a symlink swapped in the middle can't
+    /// redirect it. The configured `Fs` and `max_symlinks` are ignored; the
+    /// kernel follows at most 40 symlinks.
+    #[cfg(target_os = "linux")]
+    Kernel,
+}
+
+impl Absolutizer {
+    /// resolve the untrusted relative path against `base`, with the rules of
+    /// `openat2(2)` with `RESOLVE_BENEATH`: `Error::EscapesBase` is returned if
+    /// the path is absolute, if it (or a symlink target) goes above `base`
+    /// with `..` at any point, or if it contains an absolute symlink, even one
+    /// pointing inside `base`.
+    ///
+    /// Symlinks are always followed, regardless of `symlink_policy`, and the
+    /// path isn't expanded (`expand_tilde` and `expand_vars` only apply to
+    /// `base`). `base` is canonicalized with this configuration, so it must
+    /// exist. With `must_exist(false)`, a dangling symlink is followed with
+    /// the same rules and the missing rest is appended lexically.
+    ///
+    /// Note: a symlink replaced after this returns can still redirect a later
+    /// `open` outside of `base`.
+    pub fn resolve_beneath(
+        &self,
+        base: impl AsRef<Path>,
+        untrusted: impl AsRef<Path>,
+    ) -> Result<AbsolutePathBuf> {
+        let untrusted = untrusted.as_ref();
+        let base = self.canonicalize(base)?.into_path_buf();
+
+        let resolved = match self.backend() {
+            BeneathBackend::Fs => {
+                let max_symlinks = self.limits().max_symlinks;
+                self.walk_beneath(self.filesystem(), max_symlinks, &base, untrusted)?
+            }
+            #[cfg(target_os = "linux")]
+            BeneathBackend::Kernel => self.kernel_beneath(&base, untrusted)?,
+        };
+        self.check_depth(&resolved)?;
+        self.finish(resolved)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn kernel_beneath(&self, base: &Path, untrusted: &Path) -> Result<PathBuf> {
+        match crate::linux::to_absolute(base, untrusted) {
+            Ok(resolved) => Ok(resolved.into_path_buf()),
+            // the file may not exist yet, so walk the real filesystem instead.
+            Err(ref e)
+                if !self.requires_existing()
+                    && e.io_error_kind() == Some(io::ErrorKind::NotFound) =>
+            {
+                let max_symlinks = crate::walk::MAX_SYMLINK_EXPANSIONS;
+                self.walk_beneath(&crate::StdFs, max_symlinks, base, untrusted)
+            }
+            Err(Error::Io { ref source, .. }) if source.raw_os_error() == Some(libc::ELOOP) => {
+                Err(Error::TooManySymlinks {
+                    path: base.join(untrusted),
+                    max: crate::walk::MAX_SYMLINK_EXPANSIONS,
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// walk the untrusted path from the canonical `base` like
+    /// `RESOLVE_BENEATH`, keeping track of how deep below `base` it is.
+    fn walk_beneath(
+        &self,
+        fs: &dyn Fs,
+        max_symlinks: usize,
+        base: &Path,
+        untrusted: &Path,
+    ) -> Result<PathBuf> {
+        let escapes = || Error::EscapesBase {
+            base: base.to_path_buf(),
+            path: untrusted.to_path_buf(),
+        };
+        let not_a_directory = |path: &Path| {
+            let source = io::Error::new(io::ErrorKind::InvalidInput, "not a directory");
+            Error::io(Operation::Metadata, path, source)
+        };
+
+        let mut resolved = base.to_path_buf();
+        let mut depth = 0usize;
+        let mut kind = FileKind::Dir;
+        // once a component is missing, the rest is appended lexically.
+        let mut missing = false;
+        let mut pending = components(untrusted);
+        let mut expansions = 0;
+        while let Some(part) = pending.pop_front() {
+            let name = match Path::new(&part).components().next() {
+                Some(Component::Prefix(_)) | Some(Component::RootDir) => return Err(escapes()),
+                Some(Component::CurDir) | None => continue,
+                Some(Component::ParentDir) => None,
+                Some(Component::Normal(name)) => Some(name),
+            };
+            if kind != FileKind::Dir {
+                return Err(not_a_directory(&resolved));
+            }
+            let name = match name {
+                Some(name) => name,
+                None if depth == 0 => return Err(escapes()),
+                None => {
+                    resolved.pop();
+                    depth -= 1;
+                    continue;
+                }
+            };
+
+            let candidate = resolved.join(name);
+            let found = if missing {
+                Ok(FileKind::Dir)
+            } else {
+                fs.symlink_metadata(&candidate)
+            };
+            match found {
+                Ok(FileKind::Symlink) => {
+                    expansions += 1;
+                    if expansions > max_symlinks {
+                        return Err(Error::TooManySymlinks {
+                            path: candidate,
+                            max: max_symlinks,
+                        });
+                    }
+                    // the relative target is relative to `resolved`, the
+                    // directory containing the link.
+                    let target = fs
+                        .read_link(&candidate)
+                        .map_err(|e| Error::io(Operation::ReadLink, &candidate, e))?;
+                    for part in components(&target).into_iter().rev() {
+                        pending.push_front(part);
+                    }
+                    continue;
+                }
+                Ok(found) => kind = found,
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound && !self.requires_existing() => {
+                    missing = true
+                }
+                Err(e) => return Err(Error::io(Operation::Metadata, &candidate, e)),
+            }
+            resolved = candidate;
+            depth += 1;
+        }
+        // like the kernel, a trailing separator or `.` needs a directory.
+        if kind != FileKind::Dir && crate::absolutizer::ends_with_separator_or_dot(untrusted) {
+            return Err(not_a_directory(&resolved));
+        }
+
+        Ok(resolved)
+    }
+}
+
+/// get the absolute path for the untrusted relative path, which must stay
+/// inside `base`. See `Absolutizer::resolve_beneath`. On Linux, the kernel
+/// resolves the path (`BeneathBackend::Kernel`).
+/// Note: the file must exist.
+pub fn resolve_beneath(
+    base: impl AsRef<Path>,
+    untrusted: impl AsRef<Path>,
+) -> Result<AbsolutePathBuf> {
+    let absolutizer = Absolutizer::new();
+    #[cfg(target_os = "linux")]
+    let absolutizer = absolutizer.beneath_backend(BeneathBackend::Kernel);
+    absolutizer.resolve_beneath(base, untrusted)
+}
+
+fn components(path: &Path) -> VecDeque<OsString> {
+    path.components()
+        .map(|component| component.as_os_str().to_os_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::FakeFs;
+    use crate::{Absolutizer, Error};
+    use std::path::Path;
+
+    #[test]
+    fn test_resolve_beneath() {
+        let fs = FakeFs::new()
+            .file("/srv/www/index.html")
+            .file("/srv/www/assets/logo.png")
+            .file("/etc/passwd")
+            .symlink("assets/logo.png", "/srv/www/logo")
+            .symlink("/etc/passwd", "/srv/www/absolute")
+            .symlink("../../../etc", "/srv/www/assets/relative")
+            .symlink("/etc", "/srv/www/outside")
+            .symlink("/srv/www/index.html", "/srv/www/absolute-inside")
+            .symlink("assets/../index.html", "/srv/www/back-in")
+            .symlink("../../www/index.html", "/srv/www/assets/up")
+            .symlink("/etc/evil", "/srv/www/evil")
+            .symlink("../missing/evil", "/srv/www/relative-evil")
+            .symlink("new-inside", "/srv/www/dangling-inside");
+        let absolutizer = Absolutizer::new().fs(fs);
+        let beneath = |untrusted: &str| absolutizer.resolve_beneath("/srv/www", untrusted);
+        let escapes = |untrusted: &str| match beneath(untrusted) {
+            Err(Error::EscapesBase { base, path }) => {
+                assert_eq!(Path::new("/srv/www"), base);
+                assert_eq!(Path::new(untrusted), path);
+                true
+            }
+            _ => false,
+        };
+
+        assert_eq!(
+            Path::new("/srv/www/assets/logo.png"),
+            beneath("assets/../assets/./logo.png").unwrap()
+        );
+        assert_eq!(
+            Path::new("/srv/www/assets/logo.png"),
+            beneath("logo").unwrap()
+        );
+        assert_eq!(
+            Path::new("/srv/www/index.html"),
+            beneath("back-in").unwrap()
+        );
+        assert!(beneath("missing.html").is_err() && !escapes("missing.html"));
+        assert!(beneath("index.html/").is_err() && !escapes("index.html/"));
+
+        assert!(escapes("/etc/passwd"));
+        assert!(escapes("../www/index.html"));
+        assert!(escapes("assets/../../etc/passwd"));
+        assert!(escapes("absolute"));
+        assert!(escapes("assets/relative/passwd"));
+        assert!(escapes("outside/passwd"));
+        // the target goes above the base on the way, even though it ends inside.
+        assert!(escapes("assets/up"));
+        // like `RESOLVE_BENEATH`, whatever the limits are.
+        assert!(escapes("absolute-inside"));
+        assert!(matches!(
+            absolutizer
+                .clone()
+                .max_depth(100)
+                .resolve_beneath("/srv/www", "absolute-inside"),
+            Err(Error::EscapesBase { .. })
+        ));
+
+        // a new file under a symlink which points outside.
+        let partial = absolutizer.clone().must_exist(false);
+        assert_eq!(
+            Path::new("/srv/www/assets/new.png"),
+            partial
+                .resolve_beneath("/srv/www", "assets/new.png")
+                .unwrap()
+        );
+        assert!(matches!(
+            partial.resolve_beneath("/srv/www", "outside/new"),
+            Err(Error::EscapesBase { .. })
+        ));
+
+        // a dangling symlink is followed, so writing to the result can't
+        // create a file outside.
+        for untrusted in &["evil", "relative-evil", "evil/new"] {
+            assert!(matches!(
+                partial.resolve_beneath("/srv/www", untrusted),
+                Err(Error::EscapesBase { .. })
+            ));
+        }
+        assert_eq!(
+            Path::new("/srv/www/new-inside"),
+            partial
+                .resolve_beneath("/srv/www", "dangling-inside")
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use crate::walk::{self, CacheEntry, Walk, WalkCache};
+use crate::{Absolutizer, CanonicalPathBuf, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::MutexGuard;
+
+/// canonicalizes paths while remembering every resolved directory prefix (and
+/// symlink target), so resolving paths in the same tree again needs fewer
+/// syscalls.
+///
+/// The cache is never refreshed automatically. Call `invalidate` or `clear`
+/// when the filesystem changes.
+#[derive(Debug, Default)]
+pub struct CachingResolver {
+    absolutizer: Absolutizer,
+    cache: WalkCache,
+}
+
+impl CachingResolver {
+    pub fn new() -> CachingResolver {
+        CachingResolver::default()
+    }
+
+    /// use the base directory, the filesystem, the limits and the prefix
+    /// policy of the `absolutizer`. The result is always in the plain form,
+    /// like `Absolutizer::canonicalize`.
+    pub fn with_absolutizer(absolutizer: Absolutizer) -> CachingResolver {
+        CachingResolver {
+            absolutizer: absolutizer.canonicalizer(),
+            cache: WalkCache::default(),
+        }
+    }
+
+    /// same as `Absolutizer::canonicalize`. Paths the filesystem may
+    /// canonicalize differently from the walk (see `Fs::canonicalizes_by_walk`)
+    /// are given to `Fs::canonicalize` and not cached.
+    pub fn canonicalize(&self, path: impl AsRef<Path>) -> Result<CanonicalPathBuf> {
+        let joined = self.absolutizer.joined(path.as_ref())?;
+        if !self.absolutizer.walk_agrees(&joined) {
+            return self.absolutizer.canonicalize_joined(&joined);
+        }
+
+        let resolved = Walk::with_cache(self.absolutizer.filesystem(), &self.cache)
+            .limits(self.absolutizer.limits())
+            .canonicalize(&joined)?;
+        let resolved = self.absolutizer.finish(resolved)?;
+        Ok(CanonicalPathBuf::new_unchecked(resolved))
+    }
+
+    /// forget every entry that depends on the subtree `path` (which should be
+    /// absolute): its unresolved or resolved path is in the subtree, or it was
+    /// resolved through a symlink or a directory in it.
+    pub fn invalidate(&self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        self.lock()
+            .retain(|unresolved, entry| !unresolved.starts_with(path) && !entry.depends_on(path));
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// the number of remembered paths.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<PathBuf, CacheEntry>> {
+        walk::lock_cache(&self.cache)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CachingResolver;
+    use crate::testing::TempDir;
+    use crate::{Absolutizer, FileKind, Fs, StdFs};
+    use std::fs;
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Condvar, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_caching_resolver() {
+        // the filesystem changes during the test, so it has to be the real one.
+        let temp = TempDir::new("cache");
+        fs::create_dir_all(temp.join("a").join("sub")).unwrap();
+        fs::create_dir_all(temp.join("b").join("sub")).unwrap();
+
+        let resolver = CachingResolver::with_absolutizer(Absolutizer::new().fs(WalkingFs));
+        assert_eq!(
+            temp.join("a/sub"),
+            resolver.canonicalize(temp.join("a/./sub")).unwrap()
+        );
+        assert!(!resolver.is_empty());
+        assert_eq!(
+            temp.join("a/sub"),
+            resolver.canonicalize(temp.join("a/../a/sub")).unwrap()
+        );
+        assert!(resolver.canonicalize(temp.join("a/missing")).is_err());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::symlink;
+
+            let link = temp.join("link");
+            symlink("a", &link).unwrap();
+            assert_eq!(
+                temp.join("a/sub"),
+                resolver.canonicalize(link.join("sub")).unwrap()
+            );
+
+            // the cache keeps the old target until invalidated.
+            fs::remove_file(&link).unwrap();
+            symlink("b", &link).unwrap();
+            assert_eq!(
+                temp.join("a/sub"),
+                resolver.canonicalize(link.join("sub")).unwrap()
+            );
+            resolver.invalidate(&link);
+            assert_eq!(
+                temp.join("b/sub"),
+                resolver.canonicalize(link.join("sub")).unwrap()
+            );
+
+            // so does a path resolved through the link by another symlink.
+            let outer = temp.join("outer");
+            symlink("link/sub", &outer).unwrap();
+            assert_eq!(temp.join("b/sub"), resolver.canonicalize(&outer).unwrap());
+            fs::remove_file(&link).unwrap();
+            symlink("a", &link).unwrap();
+            resolver.invalidate(&link);
+            assert_eq!(temp.join("a/sub"), resolver.canonicalize(&outer).unwrap());
+        }
+
+        resolver.clear();
+        assert!(resolver.is_empty());
+    }
+
+    /// the real filesystem, canonicalized by the walk on every OS.
+    #[derive(Debug)]
+    struct WalkingFs;
+
+    impl Fs for WalkingFs {
+        fn symlink_metadata(&self, path: &Path) -> io::Result<FileKind> {
+            StdFs.symlink_metadata(path)
+        }
+
+        fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+            StdFs.read_link(path)
+        }
+
+        fn current_dir(&self) -> io::Result<PathBuf> {
+            StdFs.current_dir()
+        }
+
+        fn canonicalizes_by_walk(&self) -> bool {
+            true
+        }
+    }
+
+    /// a filesystem whose `symlink_metadata` waits until two calls are in
+    /// progress at once.
+    #[derive(Debug, Default)]
+    struct RendezvousFs {
+        inside: Mutex<usize>,
+        arrived: Condvar,
+    }
+
+    impl Fs for RendezvousFs {
+        fn symlink_metadata(&self, _path: &Path) -> io::Result<FileKind> {
+            let mut inside = self.inside.lock().unwrap();
+            *inside += 1;
+            self.arrived.notify_all();
+            let (inside, timeout) = self
+                .arrived
+                .wait_timeout_while(inside, Duration::from_secs(10), |inside| *inside < 2)
+                .unwrap();
+            drop(inside);
+            if timeout.timed_out() {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "alone"));
+            }
+            Ok(FileKind::Dir)
+        }
+
+        fn read_link(&self, _path: &Path) -> io::Result<PathBuf> {
+            Err(io::Error::new(io::ErrorKind::InvalidInput, "not a link"))
+        }
+
+        fn current_dir(&self) -> io::Result<PathBuf> {
+            Ok(PathBuf::from("/"))
+        }
+
+        fn canonicalizes_by_walk(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_concurrent_callers() {
+        let resolver =
+            CachingResolver::with_absolutizer(Absolutizer::new().fs(RendezvousFs::default()));
+        thread::scope(|scope| {
+            let a = scope.spawn(|| resolver.canonicalize("/a"));
+            let b = scope.spawn(|| resolver.canonicalize("/b"));
+            assert_eq!(Path::new("/a"), a.join().unwrap().unwrap());
+            assert_eq!(Path::new("/b"), b.join().unwrap().unwrap());
+        });
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{AbsolutePath, AbsolutePathBuf};
+use std::borrow::Borrow;
+use std::ffi::OsStr;
+use std::fmt;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// an absolute path which has been fully resolved on the filesystem: every
+/// symlink is followed and there is no `.` or `..`. It can only be created by
+/// the canonicalization of this crate (e.g. `canonicalize`), so two
+/// `CanonicalPathBuf`s pointing the same file compare equal (as long as the
+/// filesystem hasn't changed in between).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CanonicalPathBuf(AbsolutePathBuf);
+
+impl CanonicalPathBuf {
+    pub(crate) fn new_unchecked(path: AbsolutePathBuf) -> CanonicalPathBuf {
+        CanonicalPathBuf(path)
+    }
+
+    pub fn as_absolute_path(&self) -> &AbsolutePath {
+        &self.0
+    }
+
+    pub fn as_path(&self) -> &Path {
+        self.0.as_path()
+    }
+
+    pub fn into_absolute_path_buf(self) -> AbsolutePathBuf {
+        self.0
+    }
+
+    pub fn into_path_buf(self) -> PathBuf {
+        self.0.into_path_buf()
+    }
+}
+
+impl Deref for CanonicalPathBuf {
+    type Target = AbsolutePath;
+
+    fn deref(&self) -> &AbsolutePath {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for CanonicalPathBuf {
+    fn as_ref(&self) -> &Path {
+        self.0.as_ref()
+    }
+}
+
+impl AsRef<OsStr> for CanonicalPathBuf {
+    fn as_ref(&self) -> &OsStr {
+        self.0.as_ref()
+    }
+}
+
+impl AsRef<AbsolutePath> for CanonicalPathBuf {
+    fn as_ref(&self) -> &AbsolutePath {
+        &self.0
+    }
+}
+
+impl Borrow<AbsolutePath> for CanonicalPathBuf {
+    fn borrow(&self) -> &AbsolutePath {
+        &self.0
+    }
+}
+
+impl From<CanonicalPathBuf> for AbsolutePathBuf {
+    fn from(path: CanonicalPathBuf) -> AbsolutePathBuf {
+        path.0
+    }
+}
+
+impl From<CanonicalPathBuf> for PathBuf {
+    fn from(path: CanonicalPathBuf) -> PathBuf {
+        path.into_path_buf()
+    }
+}
+
+impl fmt::Display for CanonicalPathBuf {
+    fn fmt(&self, b: &mut fmt::Formatter) -> fmt::Result {
+        write!(b, "{}", self.0)
+    }
+}
+
+macro_rules! impl_partial_eq {
+    ($rhs:ty) => {
+        impl<'a> PartialEq<$rhs> for CanonicalPathBuf {
+            fn eq(&self, other: &$rhs) -> bool {
+                <Path as PartialEq>::eq(self.as_ref(), other.as_ref())
+            }
+        }
+
+        impl<'a> PartialEq<CanonicalPathBuf> for $rhs {
+            fn eq(&self, other: &CanonicalPathBuf) -> bool {
+                <Path as PartialEq>::eq(self.as_ref(), other.as_ref())
+            }
+        }
+    };
+}
+
+impl_partial_eq!(AbsolutePathBuf);
+impl_partial_eq!(AbsolutePath);
+impl_partial_eq!(&'a AbsolutePath);
+impl_partial_eq!(Path);
+impl_partial_eq!(&'a Path);
+impl_partial_eq!(PathBuf);
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::FakeFs;
+    use crate::Absolutizer;
+
+    #[test]
+    fn test_canonical_path() {
+        let absolutizer = Absolutizer::new().fs(FakeFs::new().dir("/temp/dir"));
+
+        let canonical = absolutizer.canonicalize("/temp/dir").unwrap();
+        assert_eq!(
+            canonical,
+            absolutizer.canonicalize("/temp/dir/../dir/.").unwrap()
+        );
+        assert_eq!(
+            canonical.parent().unwrap(),
+            absolutizer.canonicalize("/temp").unwrap()
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{canonicalize, canonicalize_partial, to_absolute, to_absolute_from_current_dir};
+use crate::{to_absolute_lexical, to_absolute_lexical_from_current_dir, to_absolute_partial};
+use crate::{to_relative, AbsolutePathBuf, Absolutizer, CanonicalPathBuf, Result};
+use std::path::{Path, PathBuf};
+
+/// method-call style access to the functions of this crate, e.g.
+/// `path.to_absolute_from(&base)?`.
+pub trait PathExt {
+    /// same as `to_absolute(base, self)`.
+    fn to_absolute_from(&self, base: impl AsRef<Path>) -> Result<AbsolutePathBuf>;
+
+    /// same as `to_absolute_from_current_dir(self)`.
+    fn absolutize(&self) -> Result<AbsolutePathBuf>;
+
+    /// same as `to_absolute_lexical(base, self)`.
+    fn to_absolute_lexical_from(&self, base: impl AsRef<Path>) -> Result<AbsolutePathBuf>;
+
+    /// same as `to_absolute_lexical_from_current_dir(self)`.
+    fn absolutize_lexical(&self) -> Result<AbsolutePathBuf>;
+
+    /// same as `to_absolute_partial(base, self)`.
+    fn to_absolute_partial_from(&self, base: impl AsRef<Path>) -> Result<AbsolutePathBuf>;
+
+    /// same as `canonicalize(self)`. Named so because `Path::canonicalize`
+    /// already exists.
+    fn canonicalize_clean(&self) -> Result<CanonicalPathBuf>;
+
+    /// same as `canonicalize_partial(self)`.
+    fn canonicalize_partial(&self) -> Result<AbsolutePathBuf>;
+
+    /// same as `to_relative(base, self)`.
+    fn relative_to(&self, base: impl AsRef<Path>) -> Result<PathBuf>;
+
+    /// same as `absolutizer.resolve(self)`.
+    fn absolutize_with(&self, absolutizer: &Absolutizer) -> Result<AbsolutePathBuf>;
+}
+
+impl<P: AsRef<Path> + ?Sized> PathExt for P {
+    fn to_absolute_from(&self, base: impl AsRef<Path>) -> Result<AbsolutePathBuf> {
+        to_absolute(base, self)
+    }
+
+    fn absolutize(&self) -> Result<AbsolutePathBuf> {
+        to_absolute_from_current_dir(self)
+    }
+
+    fn to_absolute_lexical_from(&self, base: impl AsRef<Path>) -> Result<AbsolutePathBuf> {
+        to_absolute_lexical(base, self)
+    }
+
+    fn absolutize_lexical(&self) -> Result<AbsolutePathBuf> {
+        to_absolute_lexical_from_current_dir(self)
+    }
+
+    fn to_absolute_partial_from(&self, base: impl AsRef<Path>) -> Result<AbsolutePathBuf> {
+        to_absolute_partial(base, self)
+    }
+
+    fn canonicalize_clean(&self) -> Result<CanonicalPathBuf> {
+        canonicalize(self)
+    }
+
+    fn canonicalize_partial(&self) -> Result<AbsolutePathBuf> {
+        canonicalize_partial(self)
+    }
+
+    fn relative_to(&self, base: impl AsRef<Path>) -> Result<PathBuf> {
+        to_relative(base, self)
+    }
+
+    fn absolutize_with(&self, absolutizer: &Absolutizer) -> Result<AbsolutePathBuf> {
+        absolutizer.resolve(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PathExt;
+    use std::env;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_ext() {
+        let current_dir = env::current_dir().unwrap();
+        assert_eq!(
+            current_dir.join("new.txt"),
+            Path::new("new.txt").absolutize_lexical().unwrap()
+        );
+        assert_eq!(
+            current_dir.canonicalize_clean().unwrap(),
+            ".".absolutize().unwrap()
+        );
+
+        let base = PathBuf::from(if cfg!(windows) { r#"C:\base"# } else { "/base" });
+        assert_eq!(
+            base.join("file"),
+            "dir/../file".to_absolute_lexical_from(&base).unwrap()
+        );
+    }
+}
@@ -0,0 +1,414 @@
+//! conversions between absolute paths and `file:` URLs (RFC 8089).
+//!
+//! `from_path` and `to_path` use the form of the current platform. The
+//! `posix` and `windows` variants work on plain strings on any platform.
+
+use crate::{to_absolute, AbsolutePath, AbsolutePathBuf, Error, Result};
+use crate::{WindowsPath, WindowsPrefix};
+use std::path::{Path, PathBuf};
+
+/// get the `file:` URL for the absolute path.
+pub fn from_path(path: &AbsolutePath) -> Result<String> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        Ok(format!("file://{}", encode(path.as_os_str().as_bytes())))
+    }
+    #[cfg(not(unix))]
+    {
+        let text = path.to_str().ok_or_else(|| Error::NotUnicode {
+            path: path.to_path_buf(),
+        })?;
+        if cfg!(windows) {
+            from_windows_path(text)
+        } else {
+            from_posix_path(text)
+        }
+    }
+}
+
+/// get the absolute path for the `file:` URL. `.` and `..` segments are
+/// removed.
+pub fn to_path(url: &str) -> Result<AbsolutePathBuf> {
+    #[cfg(unix)]
+    let path = {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::OsStringExt;
+
+        PathBuf::from(OsString::from_vec(posix_bytes(url)?))
+    };
+    #[cfg(windows)]
+    let path = PathBuf::from(to_windows_path(url)?);
+    // other targets (e.g. wasm) use POSIX-style paths.
+    #[cfg(not(any(unix, windows)))]
+    let path = PathBuf::from(to_posix_path(url)?);
+
+    AbsolutePathBuf::new(path)
+}
+
+/// get the absolute path for the URL reference, which is either a `file:` URL
+/// or a relative reference (e.g. `../a%20b`) resolved against `current` with
+/// `to_absolute`.
+pub fn to_path_from(current: impl AsRef<Path>, reference: &str) -> Result<AbsolutePathBuf> {
+    if has_scheme(reference) || reference.starts_with("//") {
+        let url = if reference.starts_with("//") {
+            format!("file:{}", reference)
+        } else {
+            reference.to_string()
+        };
+        return to_path(&url);
+    }
+
+    let path = decode_path(strip_query(reference), reference, false)?;
+    let path = String::from_utf8(path).map_err(|_| invalid(reference, "the path isn't UTF-8"))?;
+    to_absolute(current, path)
+}
+
+/// get the `file:` URL for the absolute POSIX path, e.g. `file:///a%20b` for
+/// `/a b`.
+pub fn from_posix_path(path: &str) -> Result<String> {
+    if !path.starts_with('/') {
+        return Err(Error::NotAbsolute {
+            path: PathBuf::from(path),
+        });
+    }
+
+    Ok(format!("file://{}", encode(path.as_bytes())))
+}
+
+/// get the `file:` URL for the absolute Windows path, e.g. `file:///C:/a%20b`
+/// for `C:\a b` and `file://server/share/a` for `\\server\share\a`.
+pub fn from_windows_path(path: &str) -> Result<String> {
+    let parsed = WindowsPath::parse(path);
+    if !parsed.is_absolute() {
+        return Err(Error::NotAbsolute {
+            path: PathBuf::from(path),
+        });
+    }
+
+    let plain = parsed.to_plain()?;
+    let rest = plain.rest().replace('\\', "/");
+    match plain.prefix() {
+        Some(&WindowsPrefix::Disk(drive)) => {
+            Ok(format!("file:///{}:{}", drive, encode(rest.as_bytes())))
+        }
+        Some(WindowsPrefix::UNC { server, share }) => Ok(format!(
+            "file://{}/{}{}",
+            encode(server.as_bytes()),
+            encode(share.as_bytes()),
+            encode(rest.as_bytes())
+        )),
+        // `to_plain` leaves only the plain prefixes, and absolute paths have one.
+        _ => Err(Error::NotAbsolute {
+            path: PathBuf::from(path),
+        }),
+    }
+}
+
+/// get the absolute POSIX path for the `file:` URL. The host must be empty or
+/// `localhost`.
+pub fn to_posix_path(url: &str) -> Result<String> {
+    String::from_utf8(posix_bytes(url)?).map_err(|_| invalid(url, "the path isn't UTF-8"))
+}
+
+/// get the absolute Windows path for the `file:` URL, with a drive letter
+/// (`file:///C:/a`) or a UNC host (`file://server/share/a`).
+pub fn to_windows_path(url: &str) -> Result<String> {
+    let (host, path) = split_url(url)?;
+    let decoded = decode_path(path, url, true)?;
+    let decoded = String::from_utf8(decoded).map_err(|_| invalid(url, "the path isn't UTF-8"))?;
+
+    if !host.is_empty() {
+        if decoded.len() < 2 {
+            return Err(invalid(url, "the UNC path has no share"));
+        }
+        return Ok(format!(r"\\{}{}", host, decoded.replace('/', r"\")));
+    }
+    // `file:////server/share` is an old form of UNC paths.
+    if decoded.starts_with("//") {
+        return Ok(decoded.replace('/', r"\"));
+    }
+
+    let mut chars = decoded.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('/'), Some(drive), Some(':')) | (Some('/'), Some(drive), Some('|'))
+            if drive.is_ascii_alphabetic() =>
+        {
+            let rest = chars.as_str();
+            let rest = if rest.is_empty() { "/" } else { rest };
+            Ok(format!("{}:{}", drive, rest.replace('/', r"\")))
+        }
+        _ => Err(invalid(url, "the path has no drive letter")),
+    }
+}
+
+fn posix_bytes(url: &str) -> Result<Vec<u8>> {
+    let (host, path) = split_url(url)?;
+    if !host.is_empty() {
+        return Err(invalid(url, "the host isn't local"));
+    }
+
+    decode_path(path, url, false)
+}
+
+fn invalid(url: &str, reason: &'static str) -> Error {
+    Error::InvalidFileUrl {
+        url: url.to_string(),
+        reason,
+    }
+}
+
+/// split the `file:` URL to the host (empty if local) and the encoded path.
+fn split_url(url: &str) -> Result<(String, &str)> {
+    if !matches!(url.get(..5), Some(scheme) if scheme.eq_ignore_ascii_case("file:")) {
+        return Err(invalid(url, "the scheme isn't `file`"));
+    }
+
+    let rest = strip_query(&url[5..]);
+    let (host, path) = match rest.strip_prefix("//") {
+        Some(rest) => match rest.find('/') {
+            Some(index) => rest.split_at(index),
+            None => (rest, "/"),
+        },
+        None if rest.starts_with('/') => ("", rest),
+        None => return Err(invalid(url, "the path isn't absolute")),
+    };
+
+    let host =
+        String::from_utf8(decode(host, url)?).map_err(|_| invalid(url, "the host isn't UTF-8"))?;
+    // the host becomes the first component of a UNC path.
+    if host.contains(['/', '\\', '\0']) {
+        return Err(invalid(url, "the host contains a separator"));
+    }
+    if host.eq_ignore_ascii_case("localhost") {
+        return Ok((String::new(), path));
+    }
+
+    Ok((host, path))
+}
+
+/// remove the query and the fragment.
+fn strip_query(reference: &str) -> &str {
+    match reference.find(['?', '#']) {
+        Some(index) => &reference[..index],
+        None => reference,
+    }
+}
+
+fn has_scheme(reference: &str) -> bool {
+    match reference.find(':') {
+        Some(index) => {
+            let scheme = &reference[..index];
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+                // a Windows drive, e.g. `C:`.
+                && scheme.len() > 1
+        }
+        None => false,
+    }
+}
+
+/// decode each segment of the path, and remove `.` and `..` segments if the
+/// path is absolute. A segment mustn't decode to a separator (or a nul), which
+/// would change the structure of the path.
+fn decode_path(path: &str, url: &str, windows: bool) -> Result<Vec<u8>> {
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        let segment = decode(segment, url)?;
+        if segment
+            .iter()
+            .any(|&b| b == b'/' || b == 0 || (windows && b == b'\\'))
+        {
+            return Err(invalid(url, "a segment contains an encoded separator"));
+        }
+        segments.push(segment);
+    }
+    if path.starts_with('/') {
+        segments = remove_dot_segments(segments, windows);
+    }
+
+    Ok(segments.join(&b'/'))
+}
+
+/// remove `.` and `..` like RFC 3986 section 5.2.4. The first segment is the
+/// empty one before the root, and `..` never removes it (or a drive letter on
+/// Windows).
+fn remove_dot_segments(segments: Vec<Vec<u8>>, windows: bool) -> Vec<Vec<u8>> {
+    let last = segments.len() - 1;
+    let mut output: Vec<Vec<u8>> = Vec::with_capacity(segments.len());
+    for (i, segment) in segments.into_iter().enumerate() {
+        let is_parent = segment == b"..";
+        if !is_parent && segment != b"." {
+            output.push(segment);
+            continue;
+        }
+        let is_drive = windows && output.len() == 2 && is_drive(&output[1]);
+        if is_parent && output.len() > 1 && !is_drive {
+            output.pop();
+        }
+        // keep the trailing slash, e.g. `/a/b/..` is `/a/`.
+        if i == last {
+            output.push(Vec::new());
+        }
+    }
+
+    output
+}
+
+fn is_drive(segment: &[u8]) -> bool {
+    match *segment {
+        [letter, b':'] | [letter, b'|'] => letter.is_ascii_alphabetic(),
+        _ => false,
+    }
+}
+
+fn decode(text: &str, url: &str) -> Result<Vec<u8>> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'%' {
+            decoded.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let hex = text
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| invalid(url, "the percent-encoding is invalid"))?;
+        decoded.push(hex);
+        i += 3;
+    }
+
+    Ok(decoded)
+}
+
+/// percent-encode everything except the characters allowed in a path by RFC
+/// 3986.
+fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len());
+    for &b in bytes {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => encoded.push(b as char),
+            b'-' | b'.' | b'_' | b'~' | b'/' | b':' | b'@' => encoded.push(b as char),
+            b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*' | b'+' | b',' | b';' | b'=' => {
+                encoded.push(b as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_posix_path, from_windows_path, to_path_from, to_posix_path, to_windows_path};
+    use crate::{canonicalize, Error};
+    use std::env;
+
+    #[test]
+    fn test_posix() {
+        assert_eq!(
+            "file:///home/me/a%20b/%E3%81%82%25.rs",
+            from_posix_path("/home/me/a b/あ%.rs").unwrap()
+        );
+        assert_eq!(
+            "/home/me/a b/あ%.rs",
+            to_posix_path("file:///home/me/a%20b/%E3%81%82%25.rs").unwrap()
+        );
+        assert_eq!("/a", to_posix_path("FILE://localhost/a?x#y").unwrap());
+        assert_eq!("/a", to_posix_path("file:/a").unwrap());
+        assert_eq!("/etc", to_posix_path("file:///a/../etc").unwrap());
+        assert_eq!("/a/c", to_posix_path("file:///a/b/%2E%2E/./c").unwrap());
+        assert_eq!("/a/", to_posix_path("file:///a/b/..").unwrap());
+        assert_eq!("/etc", to_posix_path("file:///../../etc").unwrap());
+        assert!(from_posix_path("relative").is_err());
+
+        let reason = |url: &str| match to_posix_path(url) {
+            Err(Error::InvalidFileUrl { reason, .. }) => reason,
+            other => panic!("unexpected result: {:?}", other),
+        };
+        assert_eq!("the host isn't local", reason("file://server/a"));
+        assert_eq!("the scheme isn't `file`", reason("https://example.com/a"));
+        assert_eq!(
+            "a segment contains an encoded separator",
+            reason("file:///a%2Fb")
+        );
+        assert_eq!("the percent-encoding is invalid", reason("file:///a%zz"));
+        assert_eq!("the path isn't absolute", reason("file:a"));
+    }
+
+    #[test]
+    fn test_windows() {
+        assert_eq!(
+            "file:///C:/Program%20Files/a",
+            from_windows_path(r"C:\Program Files\a").unwrap()
+        );
+        assert_eq!("file:///C:/a", from_windows_path(r"\\?\C:\a").unwrap());
+        assert_eq!(
+            "file://server/share/a",
+            from_windows_path(r"\\server\share\a").unwrap()
+        );
+        assert_eq!(
+            "file://server/share/a",
+            from_windows_path(r"\\?\UNC\server\share\a").unwrap()
+        );
+        assert!(from_windows_path(r"C:relative").is_err());
+        assert!(from_windows_path(r"\\.\COM1").is_err());
+
+        assert_eq!(
+            r"C:\Program Files\a",
+            to_windows_path("file:///C:/Program%20Files/a").unwrap()
+        );
+        assert_eq!(r"c:\a", to_windows_path("file:///c|/a").unwrap());
+        assert_eq!(r"C:\", to_windows_path("file:///C:").unwrap());
+        assert_eq!(r"C:\b", to_windows_path("file:///C:/a/../../b").unwrap());
+        assert_eq!(
+            r"\\server\share\a b",
+            to_windows_path("file://server/share/a%20b").unwrap()
+        );
+        assert_eq!(
+            r"\\server\share\a",
+            to_windows_path("file:////server/share/a").unwrap()
+        );
+        assert!(to_windows_path("file:///a").is_err());
+        assert!(to_windows_path("file:///C:/a%5Cb").is_err());
+        for url in [
+            "file://ser%5Cver/share/a",
+            "file://ser%2Fver/share/a",
+            "file://ser%00ver/share/a",
+        ] {
+            match to_windows_path(url) {
+                Err(Error::InvalidFileUrl { reason, .. }) => {
+                    assert_eq!("the host contains a separator", reason)
+                }
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_to_path_from() {
+        let current_dir = env::current_dir().unwrap();
+        assert_eq!(
+            canonicalize(current_dir.join("src")).unwrap(),
+            to_path_from(&current_dir, "./s%72c/../src?query").unwrap()
+        );
+        assert!(to_path_from(&current_dir, "https://example.com/a").is_err());
+
+        #[cfg(unix)]
+        {
+            use super::{from_path, to_path};
+            use std::path::Path;
+
+            let url = from_path(&canonicalize(&current_dir).unwrap()).unwrap();
+            assert_eq!(canonicalize(&current_dir).unwrap(), to_path(&url).unwrap());
+            assert_eq!(to_path(&url).unwrap(), to_path_from("/", &url).unwrap());
+            assert_eq!(Path::new("/etc"), to_path("file:///a/../etc").unwrap());
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use crate::walk::Walk;
+use crate::{Error, Operation, Result};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// the kind of a file, as seen without following symlinks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileKind {
+    Dir,
+    Symlink,
+    /// regular files, and anything else (e.g. sockets or devices).
+    File,
+}
+
+/// the filesystem operations needed to resolve paths. Implement this to
+/// resolve paths inside something other than the real filesystem (e.g. an
+/// archive or a test fixture).
+pub trait Fs: fmt::Debug {
+    /// same as `std::fs::symlink_metadata`, but only the kind of the file.
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FileKind>;
+
+    /// same as `std::fs::read_link`.
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf>;
+
+    /// same as `std::env::current_dir`.
+    fn current_dir(&self) -> io::Result<PathBuf>;
+
+    /// canonicalize the absolute path. The default implementation resolves
+    /// the path component by component with `symlink_metadata` and
+    /// `read_link`. Returning a relative path makes the resolution fail with
+    /// `Error::NotAbsolute`.
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf> {
+        Walk::new(self).canonicalize(path)
+    }
+
+    /// whether `canonicalize` returns the same as resolving the path
+    /// component by component with `symlink_metadata` and `read_link`, so
+    /// callers may walk the path themselves to share the work between paths
+    /// (e.g. `Absolutizer::resolve_all`). Paths with `..` or a trailing
+    /// separator are always given to `canonicalize`. Defaults to `false`.
+    fn canonicalizes_by_walk(&self) -> bool {
+        false
+    }
+}
+
+impl<F: Fs + ?Sized> Fs for &F {
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FileKind> {
+        (**self).symlink_metadata(path)
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        (**self).read_link(path)
+    }
+
+    fn current_dir(&self) -> io::Result<PathBuf> {
+        (**self).current_dir()
+    }
+
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf> {
+        (**self).canonicalize(path)
+    }
+
+    fn canonicalizes_by_walk(&self) -> bool {
+        (**self).canonicalizes_by_walk()
+    }
+}
+
+/// the real filesystem.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdFs;
+
+impl Fs for StdFs {
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FileKind> {
+        let file_type = fs::symlink_metadata(path)?.file_type();
+        Ok(if file_type.is_symlink() {
+            FileKind::Symlink
+        } else if file_type.is_dir() {
+            FileKind::Dir
+        } else {
+            FileKind::File
+        })
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::read_link(path)
+    }
+
+    fn current_dir(&self) -> io::Result<PathBuf> {
+        env::current_dir()
+    }
+
+    /// uses `std::fs::canonicalize`.
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf> {
+        fs::canonicalize(path).map_err(|e| Error::io(Operation::Canonicalize, path, e))
+    }
+
+    /// `realpath(3)` on Linux walks the path the same way. Elsewhere the OS
+    /// may also normalise the result (e.g. the case of the names or the
+    /// `\\?\` prefix).
+    fn canonicalizes_by_walk(&self) -> bool {
+        cfg!(any(target_os = "linux", target_os = "android"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Entry {
+    Dir,
+    File,
+    Symlink(PathBuf),
+}
+
+/// a filesystem which only exists in memory. All paths must be absolute.
+#[derive(Debug, Clone)]
+pub struct MemoryFs {
+    entries: BTreeMap<PathBuf, Entry>,
+    current_dir: PathBuf,
+}
+
+impl MemoryFs {
+    /// create an empty filesystem. The current directory is the root.
+    pub fn new() -> MemoryFs {
+        MemoryFs::with_root(if cfg!(windows) { r#"C:\"# } else { "/" })
+    }
+
+    /// create an empty filesystem with the root directory `root` (e.g. `C:\`).
+    /// The current directory is the root.
+    pub fn with_root(root: impl AsRef<Path>) -> MemoryFs {
+        let root = root.as_ref().to_path_buf();
+        let mut entries = BTreeMap::new();
+        entries.insert(root.clone(), Entry::Dir);
+        MemoryFs {
+            entries,
+            current_dir: root,
+        }
+    }
+
+    /// create the directory and all of its parents.
+    pub fn create_dir_all(&mut self, path: impl AsRef<Path>) -> &mut MemoryFs {
+        for ancestor in path.as_ref().ancestors() {
+            self.entries.entry(clean(ancestor)).or_insert(Entry::Dir);
+        }
+        self
+    }
+
+    /// create the file and all of its parent directories.
+    pub fn create_file(&mut self, path: impl AsRef<Path>) -> &mut MemoryFs {
+        self.insert(path.as_ref(), Entry::File)
+    }
+
+    /// create the symlink `link` pointing `target`, and all of the parent
+    /// directories of `link`. `target` doesn't need to exist.
+    pub fn symlink(&mut self, target: impl AsRef<Path>, link: impl AsRef<Path>) -> &mut MemoryFs {
+        self.insert(link.as_ref(), Entry::Symlink(target.as_ref().to_path_buf()))
+    }
+
+    /// remove the entry (but not its children).
+    pub fn remove(&mut self, path: impl AsRef<Path>) -> &mut MemoryFs {
+        self.entries.remove(&clean(path.as_ref()));
+        self
+    }
+
+    pub fn set_current_dir(&mut self, path: impl AsRef<Path>) -> &mut MemoryFs {
+        self.current_dir = path.as_ref().to_path_buf();
+        self
+    }
+
+    fn insert(&mut self, path: &Path, entry: Entry) -> &mut MemoryFs {
+        if let Some(parent) = path.parent() {
+            self.create_dir_all(parent);
+        }
+        self.entries.insert(clean(path), entry);
+        self
+    }
+
+    /// look up the entry, following symlinks in the parent directories but
+    /// not in the last component.
+    fn lookup(&self, path: &Path) -> io::Result<&Entry> {
+        let path = clean(path);
+        if let Some(entry) = self.entries.get(&path) {
+            return Ok(entry);
+        }
+
+        let not_found = || io::Error::new(io::ErrorKind::NotFound, "no such file or directory");
+        let (parent, name) = match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => (parent, name),
+            _ => return Err(not_found()),
+        };
+        match self.entries.get(parent) {
+            Some(Entry::Dir) => Err(not_found()),
+            Some(Entry::File) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not a directory",
+            )),
+            // the parent has a symlink or `..` in it, so resolve it first.
+            Some(Entry::Symlink(_)) | None => {
+                let resolved = Walk::new(self)
+                    .canonicalize(parent)
+                    .map_err(|_| not_found())?;
+                if resolved == parent {
+                    return Err(not_found());
+                }
+                self.lookup(&resolved.join(name))
+            }
+        }
+    }
+}
+
+impl Default for MemoryFs {
+    fn default() -> MemoryFs {
+        MemoryFs::new()
+    }
+}
+
+impl Fs for MemoryFs {
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FileKind> {
+        Ok(match *self.lookup(path)? {
+            Entry::Dir => FileKind::Dir,
+            Entry::File => FileKind::File,
+            Entry::Symlink(_) => FileKind::Symlink,
+        })
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        match *self.lookup(path)? {
+            Entry::Symlink(ref target) => Ok(target.clone()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not a symbolic link",
+            )),
+        }
+    }
+
+    fn current_dir(&self) -> io::Result<PathBuf> {
+        Ok(self.current_dir.clone())
+    }
+
+    fn canonicalizes_by_walk(&self) -> bool {
+        true
+    }
+}
+
+/// drop redundant separators and `.` so the path can be used as a key.
+fn clean(path: &Path) -> PathBuf {
+    path.components().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FileKind, Fs, MemoryFs};
+    use crate::{Absolutizer, Error, Result};
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_memory_fs() {
+        let mut fs = MemoryFs::new();
+        fs.create_file("/usr/share/doc/README")
+            .create_dir_all("/home/user/project")
+            .symlink("/usr/share", "/share")
+            .symlink("../share/doc", "/usr/lib/doc")
+            .symlink("/nowhere", "/dangling")
+            .set_current_dir("/home/user/project");
+
+        assert_eq!(
+            FileKind::Symlink,
+            fs.symlink_metadata(Path::new("/share")).unwrap()
+        );
+        assert_eq!(
+            FileKind::File,
+            fs.symlink_metadata(Path::new("/share/doc/README")).unwrap()
+        );
+        assert!(fs
+            .symlink_metadata(Path::new("/usr/share/doc/README/x"))
+            .is_err());
+
+        let absolutizer = Absolutizer::new().fs(fs);
+        assert_eq!(
+            Path::new("/usr/share/doc/README"),
+            absolutizer.resolve("/usr/lib/doc/README").unwrap()
+        );
+        assert_eq!(
+            Path::new("/usr/share/doc"),
+            absolutizer.resolve("../../../share/./doc").unwrap()
+        );
+        assert!(absolutizer.resolve("/dangling").is_err());
+        assert_eq!(
+            Path::new("/home/user/project/new.txt"),
+            absolutizer
+                .clone()
+                .must_exist(false)
+                .resolve("new.txt")
+                .unwrap()
+        );
+        assert_eq!(
+            Path::new("/nowhere/file"),
+            absolutizer
+                .clone()
+                .must_exist(false)
+                .resolve("/dangling/file")
+                .unwrap()
+        );
+        assert!(absolutizer
+            .clone()
+            .follow_symlinks(false)
+            .resolve("missing")
+            .is_err());
+    }
+
+    #[test]
+    fn test_relative_canonicalize() {
+        #[derive(Debug)]
+        struct BrokenFs(MemoryFs);
+
+        impl Fs for BrokenFs {
+            fn symlink_metadata(&self, path: &Path) -> io::Result<FileKind> {
+                self.0.symlink_metadata(path)
+            }
+
+            fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+                self.0.read_link(path)
+            }
+
+            fn current_dir(&self) -> io::Result<PathBuf> {
+                self.0.current_dir()
+            }
+
+            fn canonicalize(&self, _path: &Path) -> Result<PathBuf> {
+                Ok(PathBuf::from("relative/oops"))
+            }
+        }
+
+        let mut fs = MemoryFs::new();
+        fs.create_dir_all("/x");
+        match Absolutizer::new().fs(BrokenFs(fs)).resolve("/x") {
+            Err(Error::NotAbsolute { path }) => assert_eq!(Path::new("relative/oops"), path),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
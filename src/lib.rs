@@ -1,88 +1,405 @@
 use std::env;
 use std::error;
+use std::ffi::OsString;
 use std::fmt;
-use std::fs;
 use std::io;
-use std::path::{Component, Path, PathBuf, Prefix};
+use std::path::{Component, Path, PathBuf};
 use std::result;
 
+mod absolute_path;
+mod absolutizer;
+mod batch;
+mod beneath;
+mod cache;
+mod canonical_path;
+mod ext;
+pub mod file_url;
+mod fs;
+#[cfg(target_os = "linux")]
+pub mod linux;
+mod relative;
+mod rooted;
+#[cfg(feature = "serde")]
+pub mod serde_support;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod tilde;
+#[cfg(feature = "tokio")]
+pub mod tokio_support;
+mod trace;
+mod vars;
+mod walk;
+mod windows_path;
+
+pub use absolute_path::{AbsolutePath, AbsolutePathBuf};
+pub use absolutizer::{Absolutizer, OutputForm, PrefixPolicy, SymlinkPolicy};
+pub use beneath::{resolve_beneath, BeneathBackend};
+pub use cache::CachingResolver;
+pub use canonical_path::CanonicalPathBuf;
+pub use ext::PathExt;
+pub use fs::{FileKind, Fs, MemoryFs, StdFs};
+pub use relative::to_relative;
+pub use rooted::{to_absolute_in_root, RootedPath};
+pub use tilde::{expand_tilde, HomeDirs, SystemHomeDirs};
+pub use trace::{Trace, TraceStep};
+pub use vars::{expand_vars, ProcessEnv, VarSource, VarSyntax};
+pub use windows_path::{PrefixKind, WindowsPath, WindowsPrefix};
+
 pub type Result<T> = result::Result<T, Error>;
 
 #[derive(Debug)]
 pub enum Error {
-    CurrentIsRelative,
-    UnsupportedPrefix,
-    IoError(io::Error),
+    /// the path specified as current directory (or base directory) was
+    /// relative.
+    CurrentIsRelative { current: PathBuf },
+    /// the path has a Windows prefix that can't be simplified.
+    UnsupportedPrefix { path: PathBuf, prefix: PrefixKind },
+    /// the path was expected to be absolute but was relative.
+    NotAbsolute { path: PathBuf },
+    /// the paths are on different drives or shares.
+    DifferentPrefix { base: PathBuf, target: PathBuf },
+    /// the untrusted path would resolve outside of the base directory.
+    EscapesBase { base: PathBuf, path: PathBuf },
+    /// the symlink leads back to itself. `members` are the symlinks in the
+    /// loop, in the order they were followed.
+    SymlinkLoop {
+        path: PathBuf,
+        members: Vec<PathBuf>,
+    },
+    /// more than `max` symlinks were followed.
+    TooManySymlinks { path: PathBuf, max: usize },
+    /// the resolved path had more than `max` components.
+    TooDeep { path: PathBuf, max: usize },
+    /// the home directory for `~` (if `user` is `None`) or `~user` can't be
+    /// determined.
+    NoHomeDir {
+        user: Option<OsString>,
+        path: PathBuf,
+    },
+    /// the variable referenced in the path isn't defined.
+    UndefinedVariable { name: String, path: PathBuf },
+    /// the `file:` URL can't be converted to a path.
+    InvalidFileUrl { url: String, reason: &'static str },
+    /// the path isn't valid Unicode, which the conversion needs.
+    NotUnicode { path: PathBuf },
+    /// the filesystem operation on the path failed.
+    Io {
+        operation: Operation,
+        path: PathBuf,
+        source: io::Error,
+    },
+}
+
+impl Error {
+    pub(crate) fn io(operation: Operation, path: impl Into<PathBuf>, source: io::Error) -> Error {
+        Error::Io {
+            operation,
+            path: path.into(),
+            source,
+        }
+    }
+
+    /// the path which caused the error.
+    pub fn path(&self) -> &Path {
+        match *self {
+            Error::CurrentIsRelative { ref current } => current,
+            Error::UnsupportedPrefix { ref path, .. } => path,
+            Error::NotAbsolute { ref path } => path,
+            Error::DifferentPrefix { ref target, .. } => target,
+            Error::EscapesBase { ref path, .. } => path,
+            Error::SymlinkLoop { ref path, .. } => path,
+            Error::TooManySymlinks { ref path, .. } => path,
+            Error::TooDeep { ref path, .. } => path,
+            Error::NoHomeDir { ref path, .. } => path,
+            Error::UndefinedVariable { ref path, .. } => path,
+            Error::InvalidFileUrl { ref url, .. } => Path::new(url),
+            Error::NotUnicode { ref path } => path,
+            Error::Io { ref path, .. } => path,
+        }
+    }
+
+    /// the step of the resolution which failed.
+    pub fn operation(&self) -> Option<Operation> {
+        match *self {
+            Error::CurrentIsRelative { .. } => Some(Operation::Join),
+            Error::UnsupportedPrefix { .. } => Some(Operation::ConvertPrefix),
+            Error::SymlinkLoop { .. } | Error::TooManySymlinks { .. } => Some(Operation::ReadLink),
+            Error::NotAbsolute { .. }
+            | Error::DifferentPrefix { .. }
+            | Error::EscapesBase { .. }
+            | Error::TooDeep { .. }
+            | Error::NoHomeDir { .. }
+            | Error::UndefinedVariable { .. }
+            | Error::InvalidFileUrl { .. }
+            | Error::NotUnicode { .. } => None,
+            Error::Io { operation, .. } => Some(operation),
+        }
+    }
+
+    /// the kind of the underlying `io::Error`, if any.
+    pub fn io_error_kind(&self) -> Option<io::ErrorKind> {
+        match *self {
+            Error::Io { ref source, .. } => Some(source.kind()),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, b: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Error::CurrentIsRelative => write!(
+            Error::CurrentIsRelative { ref current } => write!(
+                b,
+                "the path specified as current directory was relative path: {}",
+                current.display()
+            ),
+            Error::UnsupportedPrefix { ref path, prefix } => write!(
+                b,
+                "the path specified has the prefix that isn't supported ({}): {}",
+                prefix,
+                path.display()
+            ),
+            Error::NotAbsolute { ref path } => {
+                write!(b, "the path was relative path: {}", path.display())
+            }
+            Error::DifferentPrefix {
+                ref base,
+                ref target,
+            } => write!(
                 b,
-                "the path specified as current directory was relative path."
+                "the paths have different prefixes (drives or shares) so no relative path exists: {} and {}",
+                base.display(),
+                target.display()
             ),
-            Error::UnsupportedPrefix => {
-                write!(b, "the path specified has the prefix that isn't supported.")
+            Error::EscapesBase { ref base, ref path } => write!(
+                b,
+                "the path escapes the base directory {}: {}",
+                base.display(),
+                path.display()
+            ),
+            Error::SymlinkLoop {
+                ref path,
+                ref members,
+            } => {
+                write!(b, "the symlink loops back to itself: {} (", path.display())?;
+                for (i, member) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(b, " -> ")?;
+                    }
+                    write!(b, "{}", member.display())?;
+                }
+                write!(b, ")")
             }
-            Error::IoError(ref e) => write!(b, "io::Error happened: {}", e),
+            Error::TooManySymlinks { ref path, max } => write!(
+                b,
+                "more than {} symlinks were followed: {}",
+                max,
+                path.display()
+            ),
+            Error::TooDeep { ref path, max } => write!(
+                b,
+                "the path has more than {} components: {}",
+                max,
+                path.display()
+            ),
+            Error::NoHomeDir {
+                user: Some(ref user),
+                ref path,
+            } => write!(
+                b,
+                "the home directory of {} can't be determined: {}",
+                user.to_string_lossy(),
+                path.display()
+            ),
+            Error::NoHomeDir {
+                user: None,
+                ref path,
+            } => write!(
+                b,
+                "the home directory of the current user can't be determined: {}",
+                path.display()
+            ),
+            Error::UndefinedVariable { ref name, ref path } => write!(
+                b,
+                "the variable `{}` isn't defined: {}",
+                name,
+                path.display()
+            ),
+            Error::InvalidFileUrl { ref url, reason } => {
+                write!(b, "the file URL is invalid ({}): {}", reason, url)
+            }
+            Error::NotUnicode { ref path } => {
+                write!(b, "the path isn't valid Unicode: {}", path.display())
+            }
+            Error::Io {
+                operation,
+                ref path,
+                ref source,
+            } if path.as_os_str().is_empty() => write!(b, "failed to {}: {}", operation, source),
+            Error::Io {
+                operation,
+                ref path,
+                ref source,
+            } => write!(
+                b,
+                "failed to {} `{}`: {}",
+                operation,
+                path.display(),
+                source
+            ),
         }
     }
 }
 
-impl error::Error for Error {}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io { ref source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
 
-impl From<io::Error> for Error {
-    fn from(e: io::Error) -> Error {
-        Error::IoError(e)
+/// the filesystem operation which failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    /// reading the current working directory.
+    CurrentDir,
+    /// joining the path to the current directory.
+    Join,
+    /// canonicalizing the path.
+    Canonicalize,
+    /// reading the metadata of the path (e.g. to check the existence).
+    Metadata,
+    /// reading the target of the symlink.
+    ReadLink,
+    /// converting the prefix of the path to the plain form.
+    ConvertPrefix,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, b: &mut fmt::Formatter) -> fmt::Result {
+        let operation = match *self {
+            Operation::CurrentDir => "get the current directory",
+            Operation::Join => "join",
+            Operation::Canonicalize => "canonicalize",
+            Operation::Metadata => "read the metadata of",
+            Operation::ReadLink => "read the symlink",
+            Operation::ConvertPrefix => "convert the prefix of",
+        };
+        write!(b, "{}", operation)
     }
 }
 
 /// get the absolute path for specified file.
 /// Note: the file must exist.
-pub fn to_absolute(current: impl AsRef<Path>, relative: impl AsRef<Path>) -> Result<PathBuf> {
-    let current = current.as_ref();
+pub fn to_absolute(
+    current: impl AsRef<Path>,
+    relative: impl AsRef<Path>,
+) -> Result<AbsolutePathBuf> {
     let relative = relative.as_ref();
     if relative.is_absolute() {
-        return Ok(relative.to_path_buf());
+        return Ok(AbsolutePathBuf::new_unchecked(relative.to_path_buf()));
     }
-    if !current.is_absolute() {
-        return Err(Error::CurrentIsRelative);
-    }
-
-    // here: current is absolute path, relative is relative path.
-    let joined = current.join(relative);
 
-    canonicalize(joined)
+    Absolutizer::new().base(current.as_ref()).resolve(relative)
 }
 
 /// get the absolute path for specified file, relative to current working
 /// directory.
-pub fn to_absolute_from_current_dir(relative: impl AsRef<Path>) -> Result<PathBuf> {
-    let current_dir = env::current_dir()?;
+pub fn to_absolute_from_current_dir(relative: impl AsRef<Path>) -> Result<AbsolutePathBuf> {
+    let current_dir = current_dir()?;
     to_absolute(current_dir, relative)
 }
 
-pub fn canonicalize(path: impl AsRef<Path>) -> Result<PathBuf> {
-    let canonicalized = fs::canonicalize(path.as_ref())?;
-    let components = canonicalized.components().map(|component| match component {
-        Component::Prefix(prefix) => match prefix.kind() {
-            Prefix::Disk(disk) | Prefix::VerbatimDisk(disk) => {
-                let disk = disk as char;
-                Ok(format!("{}:", disk).into())
-            }
-            _ => Err(Error::UnsupportedPrefix),
-        },
-        other => Ok(other.as_os_str().to_os_string()),
-    });
+/// get the absolute path for specified file without touching the filesystem.
+/// `.` and `..` are resolved purely on the components, so the file does not
+/// need to exist. Note: symlinks are not followed, so `link/..` is simply
+/// dropped.
+pub fn to_absolute_lexical(
+    current: impl AsRef<Path>,
+    relative: impl AsRef<Path>,
+) -> Result<AbsolutePathBuf> {
+    Absolutizer::new()
+        .base(current.as_ref())
+        .follow_symlinks(false)
+        .must_exist(false)
+        .resolve(relative)
+}
+
+/// get the absolute path for specified file relative to current working
+/// directory, without touching the filesystem (other than reading the current
+/// working directory).
+pub fn to_absolute_lexical_from_current_dir(relative: impl AsRef<Path>) -> Result<AbsolutePathBuf> {
+    let current_dir = current_dir()?;
+    to_absolute_lexical(current_dir, relative)
+}
 
-    components.collect()
+fn current_dir() -> Result<PathBuf> {
+    env::current_dir().map_err(|e| Error::io(Operation::CurrentDir, "", e))
+}
+
+/// resolve `.` and `..` in the path purely on the components. `..` at the root
+/// stays at the root, and leading `..` of relative path is kept as is.
+pub fn normalize_lexical(path: impl AsRef<Path>) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.as_ref().components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                Some(Component::ParentDir) | Some(Component::CurDir) | None => {
+                    normalized.push(component)
+                }
+            },
+            other => normalized.push(other),
+        }
+    }
+
+    normalized
+}
+
+/// get the absolute path for specified file, which may not exist yet.
+/// Symlinks are resolved for the deepest ancestor that exists, and the rest of
+/// the path is appended lexically.
+pub fn to_absolute_partial(
+    current: impl AsRef<Path>,
+    relative: impl AsRef<Path>,
+) -> Result<AbsolutePathBuf> {
+    Absolutizer::new()
+        .base(current.as_ref())
+        .must_exist(false)
+        .resolve(relative)
+}
+
+/// get the canonical path for specified file, without unusual prefix (e.g.
+/// `\\?\`) on Windows.
+/// Note: the file must exist.
+pub fn canonicalize(path: impl AsRef<Path>) -> Result<CanonicalPathBuf> {
+    Absolutizer::new().canonicalize(path)
+}
+
+/// canonicalize the path which may not exist yet. The longest existing prefix
+/// is canonicalized (so symlinks in it are resolved, including a dangling
+/// one), and the non-existent tail is appended with `.` and `..` resolved
+/// lexically.
+pub fn canonicalize_partial(path: impl AsRef<Path>) -> Result<AbsolutePathBuf> {
+    Absolutizer::new().must_exist(false).resolve(path)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::to_absolute;
     use super::Result;
+    use super::{canonicalize, canonicalize_partial, to_absolute_partial};
+    use super::{normalize_lexical, to_absolute, to_absolute_lexical, WindowsPath};
+    use crate::testing::{FakeFs, TempDir};
+    use crate::Absolutizer;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
 
     fn toabs(cur: &str, rel: &str) -> Result<String> {
         to_absolute(cur, rel).map(|x| x.display().to_string())
@@ -90,37 +407,124 @@ mod tests {
 
     #[test]
     fn test_supported() {
+        let resolve = |fs: &FakeFs, cur: &str, rel: &str| {
+            Absolutizer::new()
+                .fs(fs.clone())
+                .base(cur)
+                .resolve(rel)
+                .map(|x| x.display().to_string())
+        };
+
         if cfg!(windows) {
+            let fs = FakeFs::with_root(r#"C:\"#)
+                .dir(r#"C:\Windows\System32"#)
+                .dir(r#"C:\Windows\Fonts"#)
+                .dir(r#"C:\Program Files"#);
             assert_eq!(
                 r#"C:\Windows\System32"#,
-                toabs(r#"C:\"#, r#".\Windows\System32"#).unwrap()
+                resolve(&fs, r#"C:\"#, r#".\Windows\System32"#).unwrap()
             );
 
             assert_eq!(
                 r#"C:\Windows\System32"#,
-                toabs(r#"C:\Program Files"#, r#"..\Windows\System32"#).unwrap()
+                resolve(&fs, r#"C:\Program Files"#, r#"..\Windows\System32"#).unwrap()
             );
 
             assert_eq!(
                 r#"C:\Windows\System32"#,
-                toabs(
+                resolve(
+                    &fs,
                     r#"C:\Program Files\..\Windows\Fonts"#,
                     r#"..\..\Windows\System32"#
                 )
                 .unwrap()
             );
         } else {
-            assert_eq!("/usr/share", toabs("/", "./usr/share").unwrap());
-            assert_eq!("/usr/share", toabs("/opt", "../usr/share").unwrap());
+            let fs = FakeFs::new()
+                .dir("/usr/share")
+                .dir("/usr/local/bin")
+                .dir("/opt");
+            assert_eq!("/usr/share", resolve(&fs, "/", "./usr/share").unwrap());
+            assert_eq!("/usr/share", resolve(&fs, "/opt", "../usr/share").unwrap());
             assert_eq!(
                 "/usr/share",
-                toabs("/opt/../usr/local/bin", "../../share").unwrap()
+                resolve(&fs, "/opt/../usr/local/bin", "../../share").unwrap()
+            );
+        }
+
+        // `to_absolute` itself works on the real filesystem.
+        let current_dir = env::current_dir().unwrap();
+        assert_eq!(
+            canonicalize(current_dir.join("src")).unwrap(),
+            to_absolute(&current_dir, "./src/../src").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_lexical() {
+        let toabs_lexical =
+            |cur: &str, rel: &str| to_absolute_lexical(cur, rel).map(|x| x.display().to_string());
+
+        if cfg!(windows) {
+            assert_eq!(
+                r#"C:\not\existing\file.txt"#,
+                toabs_lexical(r#"C:\not\existing"#, r#".\dir\..\file.txt"#).unwrap()
+            );
+            assert!(toabs_lexical(r#"not\absolute"#, r#"file.txt"#).is_err());
+        } else {
+            assert_eq!(
+                "/not/existing/file.txt",
+                toabs_lexical("/not/existing", "./dir/../file.txt").unwrap()
+            );
+            assert_eq!(
+                "/file.txt",
+                toabs_lexical("/not", "../../../file.txt").unwrap()
+            );
+            assert_eq!("/a/b", toabs_lexical("/ignored", "/a/./c/../b").unwrap());
+            assert!(toabs_lexical("not/absolute", "file.txt").is_err());
+        }
+
+        assert_eq!(Path::new("../a"), normalize_lexical("../a/b/.."));
+        assert_eq!(Path::new(""), normalize_lexical("a/.."));
+    }
+
+    #[test]
+    fn test_partial() {
+        let temp = TempDir::new("partial");
+        fs::create_dir_all(temp.join("real")).unwrap();
+
+        assert_eq!(
+            temp.join("real").join("not").join("file.txt"),
+            to_absolute_partial(&temp, Path::new("real/not/existing/../file.txt")).unwrap()
+        );
+        assert_eq!(
+            temp.join("real"),
+            canonicalize_partial(temp.join("not").join("..").join("real")).unwrap()
+        );
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(temp.join("real"), temp.join("link")).unwrap();
+            assert_eq!(
+                temp.join("real").join("new"),
+                canonicalize_partial(temp.join("link").join("new")).unwrap()
+            );
+            // `..` after a symlink goes to the parent of the link target.
+            assert_eq!(
+                temp.join("new"),
+                canonicalize_partial(temp.join("link").join("..").join("new")).unwrap()
             );
         }
     }
 
     #[test]
     fn test_unsupported() {
+        // these are checked on every platform.
+        assert!(WindowsPath::parse(r#"\\?\pictures\Windows\System32"#)
+            .to_plain()
+            .is_err());
+        assert!(WindowsPath::parse(r#"\\.\COM1"#).to_plain().is_err());
+
         if cfg!(windows) {
             assert!(toabs(r#"\\?\pictures"#, r#".\Windows\System32"#).is_err());
 
@@ -0,0 +1,400 @@
+//! confined resolution on Linux, which the kernel does in one step, so a
+//! symlink swapped in the middle of the resolution can't redirect it outside
+//! of the base directory.
+//!
+//! `openat2(2)` with `RESOLVE_BENEATH` is used when the kernel supports it
+//! (Linux 5.6 or later). Otherwise, the path is walked with `O_PATH` file
+//! descriptors, which is race-free for the same reason. Either way, the
+//! resolved path is read back from `/proc/self/fd` and checked again, since
+//! the file may have been moved or deleted in the meantime.
+//!
+//! `Absolutizer::resolve_beneath` uses this with `BeneathBackend::Kernel`.
+
+use crate::walk::MAX_SYMLINK_EXPANSIONS;
+use crate::{AbsolutePathBuf, Error, Operation, Result};
+use std::collections::VecDeque;
+use std::ffi::{CString, OsStr, OsString};
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// set once `openat2` turns out to be unavailable (e.g. an old kernel or a
+/// seccomp filter), so it isn't tried again.
+static OPENAT2_UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
+/// get the absolute path for specified file, which must be beneath `current`.
+/// Symlinks are followed as long as they stay beneath `current`. Returns
+/// `Error::EscapesBase` if `relative` is absolute, or if it or a symlink in it
+/// leads outside of `current`.
+/// Note: the file must exist.
+pub fn to_absolute(
+    current: impl AsRef<Path>,
+    relative: impl AsRef<Path>,
+) -> Result<AbsolutePathBuf> {
+    resolve(current.as_ref(), relative.as_ref(), true)
+}
+
+/// same as `to_absolute`, but fails with `ELOOP` if the path contains any
+/// symlink.
+pub fn to_absolute_no_symlinks(
+    current: impl AsRef<Path>,
+    relative: impl AsRef<Path>,
+) -> Result<AbsolutePathBuf> {
+    resolve(current.as_ref(), relative.as_ref(), false)
+}
+
+fn resolve(current: &Path, relative: &Path, follow_symlinks: bool) -> Result<AbsolutePathBuf> {
+    if !current.is_absolute() {
+        return Err(Error::CurrentIsRelative {
+            current: current.to_path_buf(),
+        });
+    }
+
+    // the path read back is compared with the canonical base.
+    let canonical =
+        fs::canonicalize(current).map_err(|e| Error::io(Operation::Canonicalize, current, e))?;
+    let base = open_path(libc::AT_FDCWD, canonical.as_os_str(), libc::O_DIRECTORY)
+        .map_err(|e| Error::io(Operation::Canonicalize, current, e))?;
+    let relative_or_dot = if relative.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        relative
+    };
+    let opened = match openat2_beneath(&base, relative_or_dot, follow_symlinks) {
+        Some(opened) => opened,
+        None => walk_beneath(&base, relative_or_dot, follow_symlinks),
+    };
+    let resolved = opened
+        .and_then(|fd| opened_path(&fd, &canonical))
+        .map_err(|e| match e.raw_os_error() {
+            Some(libc::EXDEV) => Error::EscapesBase {
+                base: current.to_path_buf(),
+                path: relative.to_path_buf(),
+            },
+            _ => Error::io(Operation::Canonicalize, current.join(relative), e),
+        })?;
+
+    Ok(AbsolutePathBuf::new_unchecked(resolved))
+}
+
+/// read the path of the opened file back from `/proc`, and check that it's
+/// beneath `base` (failing with `EXDEV`) and still names the file. A deleted
+/// file's link ends with ` (deleted)`, which names nothing or another file.
+fn opened_path(fd: &OwnedFd, base: &Path) -> io::Result<PathBuf> {
+    let resolved = fs::read_link(format!("/proc/self/fd/{}", fd.as_raw_fd()))?;
+    if !resolved.starts_with(base) {
+        return Err(io::Error::from_raw_os_error(libc::EXDEV));
+    }
+
+    let opened = fs::File::from(fd.try_clone()?).metadata()?;
+    let moved = || {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "the file was moved or deleted during the resolution",
+        )
+    };
+    match fs::symlink_metadata(&resolved) {
+        Ok(found) if found.dev() == opened.dev() && found.ino() == opened.ino() => Ok(resolved),
+        Ok(_) => Err(moved()),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Err(moved()),
+        Err(e) => Err(e),
+    }
+}
+
+/// open the path with `openat2`. Returns `None` if `openat2` is unavailable.
+fn openat2_beneath(
+    base: &OwnedFd,
+    path: &Path,
+    follow_symlinks: bool,
+) -> Option<io::Result<OwnedFd>> {
+    if OPENAT2_UNAVAILABLE.load(Ordering::Relaxed) {
+        return None;
+    }
+    let path = match c_string(path.as_os_str()) {
+        Ok(path) => path,
+        Err(e) => return Some(Err(e)),
+    };
+
+    // `open_how` is non-exhaustive, so it can't be built with a literal.
+    // safe because all-zero is a valid `open_how` (no flags).
+    let mut how: libc::open_how = unsafe { mem::zeroed() };
+    how.flags = (libc::O_PATH | libc::O_CLOEXEC) as u64;
+    how.resolve = libc::RESOLVE_BENEATH | libc::RESOLVE_NO_MAGICLINKS;
+    if !follow_symlinks {
+        how.resolve |= libc::RESOLVE_NO_SYMLINKS;
+    }
+
+    // safe because `path` and `how` outlive the call and `size_of` is the size
+    // of `how`.
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_openat2,
+            base.as_raw_fd(),
+            path.as_ptr(),
+            &how as *const libc::open_how,
+            mem::size_of::<libc::open_how>(),
+        )
+    };
+    if fd >= 0 {
+        // safe because the fd was just opened and is owned by nobody else.
+        return Some(Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) }));
+    }
+
+    let e = io::Error::last_os_error();
+    match e.raw_os_error() {
+        // not implemented by the kernel, or blocked by seccomp.
+        Some(libc::ENOSYS) | Some(libc::EPERM) | Some(libc::E2BIG) => {
+            OPENAT2_UNAVAILABLE.store(true, Ordering::Relaxed);
+            None
+        }
+        // the kernel gave up because of a concurrent rename; the walk doesn't.
+        Some(libc::EAGAIN) => None,
+        _ => Some(Err(e)),
+    }
+}
+
+/// open the path one component at a time with `O_PATH | O_NOFOLLOW`, with the
+/// same rules as `RESOLVE_BENEATH`: absolute paths, absolute symlinks and `..`
+/// above the base fail with `EXDEV`.
+fn walk_beneath(base: &OwnedFd, path: &Path, follow_symlinks: bool) -> io::Result<OwnedFd> {
+    let escapes = || io::Error::from_raw_os_error(libc::EXDEV);
+
+    // the directories from the base to the current one, so `..` goes back up
+    // without looking anything up.
+    let mut dirs = vec![base.try_clone()?];
+    // the last opened file, if it isn't a directory.
+    let mut file: Option<OwnedFd> = None;
+    let mut pending: VecDeque<OsString> = components(path);
+    let mut expansions = 0;
+
+    while let Some(part) = pending.pop_front() {
+        let name = match Path::new(&part).components().next() {
+            Some(Component::Prefix(_)) | Some(Component::RootDir) => return Err(escapes()),
+            Some(Component::CurDir) | None => continue,
+            Some(Component::ParentDir) => None,
+            Some(Component::Normal(name)) => Some(name.to_os_string()),
+        };
+        if file.is_some() {
+            return Err(io::Error::from_raw_os_error(libc::ENOTDIR));
+        }
+
+        let name = match name {
+            Some(name) => name,
+            None if dirs.len() == 1 => return Err(escapes()),
+            None => {
+                dirs.pop();
+                continue;
+            }
+        };
+        let dir = dirs.last().expect("the base is never popped");
+        let fd = open_path(dir.as_raw_fd(), &name, libc::O_NOFOLLOW)?;
+        match file_type(&fd)? {
+            libc::S_IFLNK if !follow_symlinks => {
+                return Err(io::Error::from_raw_os_error(libc::ELOOP))
+            }
+            libc::S_IFLNK => {
+                expansions += 1;
+                if expansions > MAX_SYMLINK_EXPANSIONS {
+                    return Err(io::Error::from_raw_os_error(libc::ELOOP));
+                }
+                // the relative target is relative to the directory containing
+                // the link, which is still on the top of `dirs`.
+                let target = read_link(&fd)?;
+                for part in components(&target).into_iter().rev() {
+                    pending.push_front(part);
+                }
+            }
+            libc::S_IFDIR => dirs.push(fd),
+            _ => file = Some(fd),
+        }
+    }
+
+    match file {
+        Some(file) => Ok(file),
+        None => Ok(dirs.pop().expect("the base is never popped")),
+    }
+}
+
+fn components(path: &Path) -> VecDeque<OsString> {
+    path.components()
+        .map(|component| component.as_os_str().to_os_string())
+        .collect()
+}
+
+fn open_path(dir: RawFd, path: &OsStr, flags: libc::c_int) -> io::Result<OwnedFd> {
+    let path = c_string(path)?;
+    let flags = libc::O_PATH | libc::O_CLOEXEC | flags;
+    // safe because `path` is a valid C string.
+    let fd = unsafe { libc::openat(dir, path.as_ptr(), flags) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // safe because the fd was just opened and is owned by nobody else.
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// the `S_IFMT` bits of the opened file.
+fn file_type(fd: &OwnedFd) -> io::Result<libc::mode_t> {
+    // safe because `stat` is a plain C struct and is written by `fstat`.
+    let mut stat: libc::stat = unsafe { mem::zeroed() };
+    if unsafe { libc::fstat(fd.as_raw_fd(), &mut stat) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(stat.st_mode & libc::S_IFMT)
+}
+
+/// read the target of the symlink opened with `O_PATH | O_NOFOLLOW`.
+fn read_link(fd: &OwnedFd) -> io::Result<PathBuf> {
+    let mut buf = vec![0u8; 256];
+    loop {
+        // safe because `buf` is writable for `buf.len()` bytes, and the empty
+        // path refers to the fd itself.
+        let len = unsafe {
+            libc::readlinkat(
+                fd.as_raw_fd(),
+                b"\0".as_ptr() as *const libc::c_char,
+                buf.as_mut_ptr() as *mut libc::c_char,
+                buf.len(),
+            )
+        };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let len = len as usize;
+        if len < buf.len() {
+            buf.truncate(len);
+            return Ok(PathBuf::from(OsString::from_vec(buf)));
+        }
+        // the target may have been truncated.
+        buf.resize(buf.len() * 2, 0);
+    }
+}
+
+fn c_string(path: &OsStr) -> io::Result<CString> {
+    CString::new(path.as_bytes()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "path contains an interior nul byte",
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{open_path, opened_path, resolve, walk_beneath};
+    use crate::testing::TempDir;
+    use crate::{Absolutizer, BeneathBackend, Error};
+    use std::fs;
+    use std::io;
+    use std::os::unix::fs::symlink;
+    use std::path::Path;
+
+    #[test]
+    fn test_confined() {
+        let temp = TempDir::new("linux");
+        fs::create_dir_all(temp.join("base/dir")).unwrap();
+        fs::write(temp.join("base/dir/file"), "").unwrap();
+        fs::write(temp.join("secret"), "").unwrap();
+        symlink("dir/file", temp.join("base/inside")).unwrap();
+        symlink("../secret", temp.join("base/relative")).unwrap();
+        symlink(temp.join("secret"), temp.join("base/absolute")).unwrap();
+        symlink(temp.join("base/dir"), temp.join("base/absolute-inside")).unwrap();
+        let base = temp.join("base");
+
+        // both the openat2 backend (if available) and the walk.
+        let base_fd = open_path(libc::AT_FDCWD, base.as_os_str(), libc::O_DIRECTORY).unwrap();
+        let escapes = |relative: &str| {
+            let walked = walk_beneath(&base_fd, Path::new(relative), true);
+            assert_eq!(Some(libc::EXDEV), walked.unwrap_err().raw_os_error());
+            matches!(
+                resolve(&base, Path::new(relative), true),
+                Err(Error::EscapesBase { .. })
+            )
+        };
+
+        assert_eq!(
+            base.join("dir/file"),
+            resolve(&base, Path::new("dir/../dir/./file"), true).unwrap()
+        );
+        assert_eq!(
+            base.join("dir/file"),
+            resolve(&base, Path::new("inside"), true).unwrap()
+        );
+        assert!(walk_beneath(&base_fd, Path::new("inside"), true).is_ok());
+        assert_eq!(base, resolve(&base, Path::new(""), true).unwrap());
+
+        assert!(escapes("../secret"));
+        assert!(escapes("relative"));
+        assert!(escapes("absolute"));
+        assert!(escapes("/etc"));
+
+        match resolve(&base, Path::new("inside"), false) {
+            Err(Error::Io { source, .. }) => assert_eq!(Some(libc::ELOOP), source.raw_os_error()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        let walked = walk_beneath(&base_fd, Path::new("inside"), false);
+        assert_eq!(Some(libc::ELOOP), walked.unwrap_err().raw_os_error());
+        assert!(resolve(&base, Path::new("dir/file/.."), true).is_err());
+        assert!(resolve(&base, Path::new("missing"), true).is_err());
+
+        // `resolve_beneath` gives the same verdicts with either backend and
+        // any limits.
+        for absolutizer in &[
+            Absolutizer::new(),
+            Absolutizer::new().beneath_backend(BeneathBackend::Kernel),
+            Absolutizer::new()
+                .beneath_backend(BeneathBackend::Kernel)
+                .max_depth(100),
+        ] {
+            assert_eq!(
+                base.join("dir/file"),
+                absolutizer.resolve_beneath(&base, "inside").unwrap()
+            );
+            for untrusted in &["absolute-inside/file", "relative", "../base/inside"] {
+                assert!(matches!(
+                    absolutizer.resolve_beneath(&base, untrusted),
+                    Err(Error::EscapesBase { .. })
+                ));
+            }
+            assert!(absolutizer.resolve_beneath(&base, "inside/").is_err());
+
+            let partial = absolutizer.clone().must_exist(false);
+            assert_eq!(
+                base.join("dir/new"),
+                partial.resolve_beneath(&base, "dir/../dir/new").unwrap()
+            );
+            assert!(matches!(
+                partial.resolve_beneath(&base, "relative"),
+                Err(Error::EscapesBase { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_opened_path() {
+        let temp = TempDir::new("opened");
+        let file = temp.join("file");
+        fs::write(&file, "").unwrap();
+        let fd = open_path(libc::AT_FDCWD, file.as_os_str(), 0).unwrap();
+
+        assert_eq!(file, opened_path(&fd, &temp).unwrap());
+        let outside = opened_path(&fd, &temp.join("elsewhere"));
+        assert_eq!(Some(libc::EXDEV), outside.unwrap_err().raw_os_error());
+
+        // the link now ends with ` (deleted)`.
+        fs::remove_file(&file).unwrap();
+        let deleted = opened_path(&fd, &temp);
+        assert_eq!(io::ErrorKind::NotFound, deleted.unwrap_err().kind());
+
+        // another file took its place.
+        fs::write(&file, "").unwrap();
+        assert!(opened_path(&fd, &temp).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use crate::{Absolutizer, Error, Result, WindowsPrefix};
+use std::path::{Component, Path, PathBuf};
+
+/// get the path of `target` as seen from the directory `base`, e.g.
+/// `../lib/foo.rs`. Both paths are absolutized first (relative to the current
+/// working directory), with symlinks resolved as far as the paths exist.
+pub fn to_relative(base: impl AsRef<Path>, target: impl AsRef<Path>) -> Result<PathBuf> {
+    Absolutizer::new().must_exist(false).relative(base, target)
+}
+
+impl Absolutizer {
+    /// get the path of `target` as seen from the directory `base`. Both paths
+    /// are resolved with this configuration first.
+    pub fn relative(&self, base: impl AsRef<Path>, target: impl AsRef<Path>) -> Result<PathBuf> {
+        let base = self.resolve(base)?;
+        let target = self.resolve(target)?;
+        diff_paths(&base, &target)
+    }
+}
+
+/// compute the shortest relative path from `base` to `target`. Both must be
+/// absolute and normalized.
+fn diff_paths(base: &Path, target: &Path) -> Result<PathBuf> {
+    let different_prefix = || Error::DifferentPrefix {
+        base: base.to_path_buf(),
+        target: target.to_path_buf(),
+    };
+
+    let mut base_components = base.components().peekable();
+    let mut target_components = target.components().peekable();
+
+    // paths on different drives or shares can't be reached with `..`.
+    match (base_components.peek(), target_components.peek()) {
+        (Some(Component::Prefix(b)), Some(Component::Prefix(t))) => {
+            let b = b.as_os_str().to_string_lossy();
+            let t = t.as_os_str().to_string_lossy();
+            if !same_prefix(&b, &t) {
+                return Err(different_prefix());
+            }
+        }
+        (Some(Component::Prefix(_)), _) | (_, Some(Component::Prefix(_))) => {
+            return Err(different_prefix())
+        }
+        _ => {}
+    }
+
+    while let (Some(b), Some(t)) = (base_components.peek(), target_components.peek()) {
+        let same = match (b, t) {
+            (Component::Prefix(_), Component::Prefix(_)) => true,
+            (b, t) => b == t,
+        };
+        if !same {
+            break;
+        }
+        base_components.next();
+        target_components.next();
+    }
+
+    let mut relative: PathBuf = base_components.map(|_| Component::ParentDir).collect();
+    relative.extend(target_components);
+    if relative.as_os_str().is_empty() {
+        relative.push(Component::CurDir);
+    }
+
+    Ok(relative)
+}
+
+/// whether the Windows paths start with the same drive or share. The verbatim
+/// and plain forms of a prefix are the same, and case doesn't matter.
+fn same_prefix(base: &str, target: &str) -> bool {
+    let prefix = |path: &str| {
+        WindowsPrefix::parse(path).map(|(prefix, _)| {
+            let prefix = prefix.plain().unwrap_or(prefix);
+            prefix.to_string().to_lowercase()
+        })
+    };
+
+    prefix(base) == prefix(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::same_prefix;
+    use crate::testing::FakeFs;
+    use crate::Absolutizer;
+    use std::path::Path;
+
+    #[test]
+    fn test_relative() {
+        let lexical = Absolutizer::new().follow_symlinks(false).must_exist(false);
+        let rel = |base: &str, target: &str| lexical.relative(base, target).unwrap();
+
+        if cfg!(windows) {
+            assert_eq!(
+                Path::new(r#"..\lib\foo.rs"#),
+                rel(r#"C:\project\src"#, r#"c:\project\lib\foo.rs"#)
+            );
+            assert!(lexical.relative(r#"C:\project"#, r#"D:\project"#).is_err());
+            assert!(lexical
+                .relative(r#"\\server\a\project"#, r#"\\server\b\project"#)
+                .is_err());
+        } else {
+            assert_eq!(
+                Path::new("../lib/foo.rs"),
+                rel("/project/src", "/project/lib/foo.rs")
+            );
+            assert_eq!(Path::new("."), rel("/project/src", "/project/./src"));
+            assert_eq!(Path::new("src"), rel("/project", "/project/src"));
+            assert_eq!(Path::new("../.."), rel("/project/src/bin", "/project"));
+            assert_eq!(Path::new("../../usr"), rel("/project/src", "/usr"));
+        }
+    }
+
+    #[test]
+    fn test_same_prefix() {
+        assert!(same_prefix(r"C:\project", r"c:\project\lib"));
+        assert!(same_prefix(r"\\?\C:\project", r"C:\lib"));
+        assert!(same_prefix(r"\\server\a\x", r"\\?\UNC\SERVER\A\y"));
+        assert!(!same_prefix(r"C:\project", r"D:\project"));
+        assert!(!same_prefix(r"\\server\a\project", r"\\server\b\project"));
+        assert!(!same_prefix(r"C:\project", r"\\server\c\project"));
+        assert!(!same_prefix(r"\\?\pictures", r"\\?\videos"));
+    }
+
+    #[test]
+    fn test_relative_existing() {
+        let fs = FakeFs::new()
+            .dir("/temp/a/b")
+            .symlink("/temp/a/b", "/temp/link");
+        // the same configuration as `to_relative`.
+        let absolutizer = Absolutizer::new().fs(fs).must_exist(false);
+
+        assert_eq!(
+            Path::new("../../c/new.txt"),
+            absolutizer
+                .relative("/temp/a/b", "/temp/a/../c/new.txt")
+                .unwrap()
+        );
+        // `..` after the symlink goes to the parent of its target.
+        assert_eq!(
+            Path::new("../new.txt"),
+            absolutizer
+                .relative("/temp/a/b", "/temp/link/../new.txt")
+                .unwrap()
+        );
+    }
+}
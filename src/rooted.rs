@@ -0,0 +1,201 @@
+use crate::{AbsolutePath, AbsolutePathBuf, Absolutizer, Error, FileKind, Fs, Result};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// a path resolved inside a root directory (e.g. a sysroot or a container
+/// rootfs).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RootedPath {
+    host: AbsolutePathBuf,
+    in_root: PathBuf,
+}
+
+impl RootedPath {
+    /// the path on the host, i.e. under the root directory.
+    pub fn host(&self) -> &AbsolutePath {
+        &self.host
+    }
+
+    /// the path as seen from inside the root, which always starts with `/`.
+    pub fn in_root(&self) -> &Path {
+        &self.in_root
+    }
+
+    pub fn into_host(self) -> AbsolutePathBuf {
+        self.host
+    }
+}
+
+impl Absolutizer {
+    /// resolve the path as if `root` were `/`, like `chroot(2)`. Absolute
+    /// paths and absolute symlink targets start at `root`, and `..` never goes
+    /// above it. Relative paths are resolved against the base directory
+    /// interpreted inside the root, or against the root if no base is set.
+    ///
+    /// `root` itself is canonicalized on the host, so it must exist. A
+    /// relative `root` is relative to the host's current directory, since the
+    /// base directory is inside the root.
+    pub fn resolve_in_root(
+        &self,
+        root: impl AsRef<Path>,
+        path: impl AsRef<Path>,
+    ) -> Result<RootedPath> {
+        let mut joined = PathBuf::from(Component::RootDir.as_os_str());
+        if let Some(base) = self.explicit_base() {
+            if !base.is_absolute() {
+                return Err(Error::CurrentIsRelative {
+                    current: base.to_path_buf(),
+                });
+            }
+            joined.push(base);
+        }
+        joined.push(path);
+
+        let root = self.without_base().canonicalize(root)?.into_path_buf();
+        let rooted = RootedFs {
+            fs: self.shared_fs(),
+            root: root.clone(),
+        };
+
+        let in_root = self.clone().fs(rooted).resolve_joined(&joined)?;
+        let host = AbsolutePathBuf::new_unchecked(under_root(&root, &in_root));
+        Ok(RootedPath { host, in_root })
+    }
+}
+
+/// get the absolute path for specified file inside `root`, where `current` is
+/// the current directory inside the root. See `Absolutizer::resolve_in_root`.
+pub fn to_absolute_in_root(
+    root: impl AsRef<Path>,
+    current: impl AsRef<Path>,
+    relative: impl AsRef<Path>,
+) -> Result<RootedPath> {
+    Absolutizer::new()
+        .base(current.as_ref())
+        .resolve_in_root(root, relative)
+}
+
+/// the filesystem inside the root. Paths given to it are in-root paths.
+#[derive(Debug)]
+struct RootedFs {
+    fs: Arc<dyn Fs + Send + Sync>,
+    root: PathBuf,
+}
+
+impl Fs for RootedFs {
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FileKind> {
+        self.fs.symlink_metadata(&under_root(&self.root, path))
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        // an absolute target is interpreted by the walk, which starts it at
+        // the in-root `/`.
+        self.fs.read_link(&under_root(&self.root, path))
+    }
+
+    fn current_dir(&self) -> io::Result<PathBuf> {
+        Ok(PathBuf::from(Component::RootDir.as_os_str()))
+    }
+}
+
+/// map the resolved in-root path to the host path.
+fn under_root(root: &Path, in_root: &Path) -> PathBuf {
+    let mut host = root.to_path_buf();
+    host.extend(
+        in_root
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_))),
+    );
+    host
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_absolute_in_root;
+    use crate::testing::FakeFs;
+    use crate::{Absolutizer, Error};
+    use std::path::Path;
+
+    #[test]
+    fn test_resolve_in_root() {
+        let fs = FakeFs::new()
+            .file("/sysroot/usr/lib/libc.so")
+            .file("/usr/lib/host-only.so")
+            .dir("/sysroot/etc")
+            .symlink("/usr/lib", "/sysroot/lib")
+            .symlink("../../../../../etc", "/sysroot/usr/escape")
+            .symlink("/lib/host-only.so", "/sysroot/usr/lib/host");
+        let absolutizer = Absolutizer::new().fs(fs);
+
+        let resolved = absolutizer
+            .resolve_in_root("/sysroot", "/lib/libc.so")
+            .unwrap();
+        assert_eq!(Path::new("/usr/lib/libc.so"), resolved.in_root());
+        assert_eq!(Path::new("/sysroot/usr/lib/libc.so"), resolved.host());
+
+        // `..` and symlinks never leave the root.
+        let resolved = absolutizer
+            .resolve_in_root("/sysroot", "usr/escape")
+            .unwrap();
+        assert_eq!(Path::new("/etc"), resolved.in_root());
+        assert_eq!(Path::new("/sysroot/etc"), resolved.host());
+        let resolved = absolutizer
+            .resolve_in_root("/sysroot", "../../etc")
+            .unwrap();
+        assert_eq!(Path::new("/sysroot/etc"), resolved.host());
+        assert!(absolutizer
+            .resolve_in_root("/sysroot", "usr/lib/host")
+            .is_err());
+
+        let resolved = absolutizer
+            .clone()
+            .base("/usr")
+            .must_exist(false)
+            .resolve_in_root("/sysroot", "lib/new.so")
+            .unwrap();
+        assert_eq!(Path::new("/sysroot/usr/lib/new.so"), resolved.host());
+    }
+
+    #[test]
+    fn test_relative_root() {
+        let fs = FakeFs::new()
+            .file("/host/sysroot/usr/lib/libc.so")
+            .file("/usr/sysroot/usr/lib/libc.so")
+            .with_current_dir("/host");
+        let absolutizer = Absolutizer::new().fs(fs);
+
+        // the root is relative to the host's current directory, not the base.
+        let resolved = absolutizer
+            .clone()
+            .base("/usr")
+            .resolve_in_root("sysroot", "lib/libc.so")
+            .unwrap();
+        assert_eq!(Path::new("/usr/lib/libc.so"), resolved.in_root());
+        assert_eq!(Path::new("/host/sysroot/usr/lib/libc.so"), resolved.host());
+
+        match absolutizer
+            .base("usr")
+            .resolve_in_root("sysroot", "lib/libc.so")
+        {
+            Err(Error::CurrentIsRelative { current }) => assert_eq!(Path::new("usr"), current),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_to_absolute_in_root() {
+        use crate::testing::TempDir;
+        use std::fs;
+        use std::os::unix::fs::symlink;
+
+        let temp = TempDir::new("rooted");
+        fs::create_dir_all(temp.join("usr/lib")).unwrap();
+        symlink("/usr/lib", temp.join("lib")).unwrap();
+
+        let resolved = to_absolute_in_root(&temp, "/usr", "../lib").unwrap();
+        assert_eq!(Path::new("/usr/lib"), resolved.in_root());
+        assert_eq!(temp.join("usr/lib"), resolved.host());
+    }
+}
@@ -0,0 +1,155 @@
+//! serde support, enabled by the `serde` feature.
+//!
+//! `AbsolutePathBuf` and `CanonicalPathBuf` are serialized as strings.
+//! Deserializing them rejects relative paths; use `AbsolutePathSeed` or
+//! `deserialize_from_current_dir` to absolutize relative paths instead.
+
+use crate::{canonicalize, to_absolute, to_absolute_from_current_dir};
+use crate::{AbsolutePath, AbsolutePathBuf, CanonicalPathBuf, Error};
+use serde::de::{self, DeserializeSeed, Deserializer};
+use serde::ser::{self, SerializeStruct, Serializer};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+fn serialize_path<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    match path.to_str() {
+        Some(path) => serializer.serialize_str(path),
+        None => Err(ser::Error::custom("path contains invalid UTF-8 characters")),
+    }
+}
+
+impl Serialize for AbsolutePath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_path(self, serializer)
+    }
+}
+
+impl Serialize for AbsolutePathBuf {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_path(self, serializer)
+    }
+}
+
+impl Serialize for CanonicalPathBuf {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_path(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for AbsolutePathBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<AbsolutePathBuf, D::Error> {
+        let path = PathBuf::deserialize(deserializer)?;
+        AbsolutePathBuf::new(path).map_err(de::Error::custom)
+    }
+}
+
+/// the path must be absolute, and is canonicalized while deserializing, so the
+/// file must exist at that time.
+impl<'de> Deserialize<'de> for CanonicalPathBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<CanonicalPathBuf, D::Error> {
+        let path = AbsolutePathBuf::deserialize(deserializer)?;
+        canonicalize(path).map_err(de::Error::custom)
+    }
+}
+
+/// serialized as a struct with the variant name (`kind`), the path which
+/// caused the error (`path`) and the message (`message`).
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let kind = match *self {
+            Error::CurrentIsRelative { .. } => "CurrentIsRelative",
+            Error::UnsupportedPrefix { .. } => "UnsupportedPrefix",
+            Error::NotAbsolute { .. } => "NotAbsolute",
+            Error::DifferentPrefix { .. } => "DifferentPrefix",
+            Error::EscapesBase { .. } => "EscapesBase",
+            Error::SymlinkLoop { .. } => "SymlinkLoop",
+            Error::TooManySymlinks { .. } => "TooManySymlinks",
+            Error::TooDeep { .. } => "TooDeep",
+            Error::NoHomeDir { .. } => "NoHomeDir",
+            Error::UndefinedVariable { .. } => "UndefinedVariable",
+            Error::InvalidFileUrl { .. } => "InvalidFileUrl",
+            Error::NotUnicode { .. } => "NotUnicode",
+            Error::Io { .. } => "Io",
+        };
+
+        let mut state = serializer.serialize_struct("Error", 3)?;
+        state.serialize_field("kind", kind)?;
+        state.serialize_field("path", &self.path().to_string_lossy())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+/// deserialize a path, absolutizing it against `base` with `to_absolute` if
+/// it's relative.
+#[derive(Debug, Clone, Copy)]
+pub struct AbsolutePathSeed<'a> {
+    base: &'a Path,
+}
+
+impl<'a> AbsolutePathSeed<'a> {
+    pub fn new(base: &'a Path) -> AbsolutePathSeed<'a> {
+        AbsolutePathSeed { base }
+    }
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for AbsolutePathSeed<'a> {
+    type Value = AbsolutePathBuf;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<AbsolutePathBuf, D::Error> {
+        let path = PathBuf::deserialize(deserializer)?;
+        to_absolute(self.base, path).map_err(de::Error::custom)
+    }
+}
+
+/// deserialize a path, absolutizing it against the current working directory
+/// with `to_absolute_from_current_dir` if it's relative. Intended for
+/// `#[serde(deserialize_with = "...")]`.
+pub fn deserialize_from_current_dir<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<AbsolutePathBuf, D::Error> {
+    let path = PathBuf::deserialize(deserializer)?;
+    to_absolute_from_current_dir(path).map_err(de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{deserialize_from_current_dir, AbsolutePathSeed};
+    use crate::{canonicalize, AbsolutePathBuf, CanonicalPathBuf};
+    use serde::de::DeserializeSeed;
+    use std::env;
+
+    #[test]
+    fn test_serde() {
+        let current_dir = canonicalize(env::current_dir().unwrap()).unwrap();
+        let json = serde_json::to_string(&current_dir).unwrap();
+
+        let absolute: AbsolutePathBuf = serde_json::from_str(&json).unwrap();
+        assert_eq!(current_dir, absolute);
+        let canonical: CanonicalPathBuf = serde_json::from_str(&json).unwrap();
+        assert_eq!(current_dir, canonical);
+
+        assert!(serde_json::from_str::<AbsolutePathBuf>(r#""relative""#).is_err());
+
+        let mut deserializer = serde_json::Deserializer::from_str(r#""src""#);
+        let seeded = AbsolutePathSeed::new(&current_dir)
+            .deserialize(&mut deserializer)
+            .unwrap();
+        assert_eq!(current_dir.join("src"), seeded);
+
+        let mut deserializer = serde_json::Deserializer::from_str(r#""src""#);
+        let from_current_dir = deserialize_from_current_dir(&mut deserializer).unwrap();
+        assert_eq!(current_dir.join("src"), from_current_dir);
+    }
+
+    #[test]
+    fn test_serialize_error() {
+        let error = AbsolutePathBuf::new("relative".into()).unwrap_err();
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!("NotAbsolute", json["kind"]);
+        assert_eq!("relative", json["path"]);
+    }
+}
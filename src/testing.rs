@@ -0,0 +1,215 @@
+//! an in-memory filesystem for hermetic tests, enabled by the `testing`
+//! feature.
+//!
+//! ```
+//! use to_absolute::testing::FakeFs;
+//! use to_absolute::Absolutizer;
+//! use std::path::Path;
+//!
+//! let fs = FakeFs::new()
+//!     .file("/usr/share/doc/README")
+//!     .symlink("/usr/share", "/share")
+//!     .with_current_dir("/home/user");
+//! let absolutizer = Absolutizer::new().fs(fs.clone());
+//! assert_eq!(
+//!     Path::new("/usr/share/doc/README"),
+//!     absolutizer.resolve("/share/doc/README").unwrap()
+//! );
+//! assert!(fs.calls() > 0);
+//! ```
+
+use crate::{FileKind, Fs, MemoryFs};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[cfg(test)]
+use std::{env, fs, ops::Deref, process};
+
+/// a `MemoryFs` with a builder, injected errors and a call counter.
+///
+/// Clones share the call counter, so a clone passed to `Absolutizer::fs` can
+/// be inspected after resolving.
+#[derive(Debug, Clone, Default)]
+pub struct FakeFs {
+    fs: MemoryFs,
+    failures: HashMap<PathBuf, io::ErrorKind>,
+    calls: Arc<AtomicUsize>,
+}
+
+impl FakeFs {
+    /// create an empty filesystem. The current directory is the root.
+    pub fn new() -> FakeFs {
+        FakeFs::default()
+    }
+
+    /// create an empty filesystem with the root directory `root` (e.g. `C:\`).
+    pub fn with_root(root: impl AsRef<Path>) -> FakeFs {
+        FakeFs {
+            fs: MemoryFs::with_root(root),
+            ..FakeFs::default()
+        }
+    }
+
+    /// add the directory and all of its parents.
+    pub fn dir(mut self, path: impl AsRef<Path>) -> FakeFs {
+        self.fs.create_dir_all(path);
+        self
+    }
+
+    /// add the file and all of its parent directories.
+    pub fn file(mut self, path: impl AsRef<Path>) -> FakeFs {
+        self.fs.create_file(path);
+        self
+    }
+
+    /// add the symlink `link` pointing `target`. `target` may be missing (a
+    /// dangling link) or lead back to `link` (a loop).
+    pub fn symlink(mut self, target: impl AsRef<Path>, link: impl AsRef<Path>) -> FakeFs {
+        self.fs.symlink(target, link);
+        self
+    }
+
+    pub fn with_current_dir(mut self, path: impl AsRef<Path>) -> FakeFs {
+        self.fs.set_current_dir(path);
+        self
+    }
+
+    /// make every operation on exactly `path` fail with `kind`. If `path` is
+    /// the current directory, reading the current directory fails too.
+    pub fn fail(mut self, path: impl AsRef<Path>, kind: io::ErrorKind) -> FakeFs {
+        self.failures.insert(clean(path.as_ref()), kind);
+        self
+    }
+
+    /// the number of `symlink_metadata`, `read_link` and `current_dir` calls
+    /// made by this filesystem and its clones.
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    pub fn reset_calls(&self) {
+        self.calls.store(0, Ordering::SeqCst);
+    }
+
+    fn enter(&self, path: &Path) -> io::Result<()> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        match self.failures.get(&clean(path)) {
+            Some(&kind) => Err(io::Error::new(kind, "injected error")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Fs for FakeFs {
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FileKind> {
+        self.enter(path)?;
+        self.fs.symlink_metadata(path)
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        self.enter(path)?;
+        self.fs.read_link(path)
+    }
+
+    fn current_dir(&self) -> io::Result<PathBuf> {
+        let current_dir = self.fs.current_dir()?;
+        self.enter(&current_dir)?;
+        Ok(current_dir)
+    }
+
+    fn canonicalizes_by_walk(&self) -> bool {
+        self.fs.canonicalizes_by_walk()
+    }
+}
+
+fn clean(path: &Path) -> PathBuf {
+    path.components().collect()
+}
+
+/// a canonicalized temporary directory for the crate's tests which need the
+/// real filesystem. It's removed on drop, even if the test fails.
+#[cfg(test)]
+pub(crate) struct TempDir(PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    /// create an empty directory. `name` must be unique among the tests.
+    pub(crate) fn new(name: &str) -> TempDir {
+        let path = env::temp_dir().join(format!("to_absolute-{}-{}", name, process::id()));
+        // left over from an aborted run.
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(fs::canonicalize(path).unwrap())
+    }
+}
+
+#[cfg(test)]
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FakeFs;
+    use crate::{Absolutizer, Operation};
+    use std::io;
+    use std::path::Path;
+
+    #[test]
+    fn test_fake_fs() {
+        let fs = FakeFs::new()
+            .dir("/srv/data")
+            .symlink("/srv/data", "/data")
+            .symlink("loop-b", "/srv/loop-a")
+            .symlink("loop-a", "/srv/loop-b")
+            .symlink("/srv/missing", "/srv/dangling")
+            .fail("/srv/locked", io::ErrorKind::PermissionDenied)
+            .with_current_dir("/srv");
+        let absolutizer = Absolutizer::new().fs(fs.clone());
+
+        assert_eq!(
+            Path::new("/srv/data"),
+            absolutizer.resolve("../data").unwrap()
+        );
+        let calls = fs.calls();
+        assert!(calls > 0);
+        fs.reset_calls();
+        assert_eq!(0, fs.calls());
+
+        let error = absolutizer.resolve("loop-a").unwrap_err();
+        assert_eq!(Some(Operation::ReadLink), error.operation());
+
+        let error = absolutizer.resolve("dangling").unwrap_err();
+        assert_eq!(Some(io::ErrorKind::NotFound), error.io_error_kind());
+
+        let error = absolutizer.resolve("locked/file").unwrap_err();
+        assert_eq!(Some(io::ErrorKind::PermissionDenied), error.io_error_kind());
+
+        let error = Absolutizer::new()
+            .fs(fs.fail("/srv", io::ErrorKind::NotFound))
+            .resolve("data")
+            .unwrap_err();
+        assert_eq!(Some(Operation::CurrentDir), error.operation());
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{Error, Result};
+use std::env;
+use std::ffi::OsStr;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+/// looks up home directories for tilde expansion.
+pub trait HomeDirs: fmt::Debug {
+    /// the home directory of the current user, for `~`.
+    fn home_dir(&self) -> Option<PathBuf>;
+
+    /// the home directory of `user`, for `~user`.
+    fn user_home_dir(&self, user: &OsStr) -> Option<PathBuf>;
+}
+
+/// `$HOME` (or `%USERPROFILE%` on Windows) and the passwd database.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemHomeDirs;
+
+impl HomeDirs for SystemHomeDirs {
+    fn home_dir(&self) -> Option<PathBuf> {
+        let var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+        match env::var_os(var) {
+            Some(home) if !home.is_empty() => Some(PathBuf::from(home)),
+            _ => current_user_home(),
+        }
+    }
+
+    fn user_home_dir(&self, user: &OsStr) -> Option<PathBuf> {
+        user_home(user)
+    }
+}
+
+/// replace the leading `~` or `~user` of the path with the home directory.
+/// Other paths are returned as is.
+pub fn expand_tilde(path: impl AsRef<Path>) -> Result<PathBuf> {
+    expand_with(path.as_ref(), &SystemHomeDirs)
+}
+
+pub(crate) fn expand_with(path: &Path, home_dirs: &dyn HomeDirs) -> Result<PathBuf> {
+    let mut components = path.components();
+    let first = match components.next() {
+        Some(Component::Normal(first)) => first,
+        _ => return Ok(path.to_path_buf()),
+    };
+    let user = match first.to_str() {
+        Some("~") => None,
+        Some(first) if first.starts_with('~') => Some(OsStr::new(&first[1..])),
+        // user names are UTF-8 in practice.
+        _ => return Ok(path.to_path_buf()),
+    };
+
+    let home = match user {
+        None => home_dirs.home_dir(),
+        Some(user) => home_dirs.user_home_dir(user),
+    };
+    match home {
+        Some(home) => Ok(home.join(components.as_path())),
+        None => Err(Error::NoHomeDir {
+            user: user.map(OsStr::to_os_string),
+            path: path.to_path_buf(),
+        }),
+    }
+}
+
+#[cfg(unix)]
+fn current_user_home() -> Option<PathBuf> {
+    // safe because `getuid` always succeeds.
+    let uid = unsafe { libc::getuid() };
+    passwd::home(|pwd, buf, len, result| unsafe { libc::getpwuid_r(uid, pwd, buf, len, result) })
+}
+
+#[cfg(not(unix))]
+fn current_user_home() -> Option<PathBuf> {
+    None
+}
+
+#[cfg(unix)]
+fn user_home(user: &OsStr) -> Option<PathBuf> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let user = CString::new(user.as_bytes()).ok()?;
+    passwd::home(|pwd, buf, len, result| unsafe {
+        libc::getpwnam_r(user.as_ptr(), pwd, buf, len, result)
+    })
+}
+
+#[cfg(not(unix))]
+fn user_home(_user: &OsStr) -> Option<PathBuf> {
+    None
+}
+
+#[cfg(unix)]
+mod passwd {
+    use std::ffi::{CStr, OsStr};
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::PathBuf;
+    use std::ptr;
+
+    /// the home directory from the passwd entry found by `lookup`, which is
+    /// `getpwuid_r` or `getpwnam_r` with the other arguments bound.
+    pub(super) fn home<F>(lookup: F) -> Option<PathBuf>
+    where
+        F: Fn(*mut libc::passwd, *mut libc::c_char, usize, *mut *mut libc::passwd) -> libc::c_int,
+    {
+        let mut buf: Vec<libc::c_char> = vec![0; 1024];
+        loop {
+            // safe because `passwd` is a plain C struct and is written by
+            // `lookup`.
+            let mut pwd: libc::passwd = unsafe { mem::zeroed() };
+            let mut result = ptr::null_mut();
+            match lookup(&mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) {
+                libc::ERANGE if buf.len() < 1 << 20 => {
+                    let len = buf.len() * 2;
+                    buf.resize(len, 0);
+                }
+                0 if !result.is_null() && !pwd.pw_dir.is_null() => {
+                    // safe because `pw_dir` points into `buf`, which is still
+                    // alive.
+                    let dir = unsafe { CStr::from_ptr(pwd.pw_dir) };
+                    return Some(PathBuf::from(OsStr::from_bytes(dir.to_bytes())));
+                }
+                _ => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HomeDirs, SystemHomeDirs};
+    use crate::{Absolutizer, Error};
+    use std::collections::HashMap;
+    use std::ffi::{OsStr, OsString};
+    use std::path::{Path, PathBuf};
+
+    #[derive(Debug)]
+    struct FakeHomeDirs(HashMap<OsString, PathBuf>);
+
+    impl HomeDirs for FakeHomeDirs {
+        fn home_dir(&self) -> Option<PathBuf> {
+            self.user_home_dir(OsStr::new("me"))
+        }
+
+        fn user_home_dir(&self, user: &OsStr) -> Option<PathBuf> {
+            self.0.get(user).cloned()
+        }
+    }
+
+    #[test]
+    fn test_tilde() {
+        let mut homes = HashMap::new();
+        homes.insert(OsString::from("me"), PathBuf::from("/home/me"));
+        homes.insert(OsString::from("bob"), PathBuf::from("/home/bob"));
+        let lexical = Absolutizer::new()
+            .base("/work")
+            .follow_symlinks(false)
+            .must_exist(false);
+        let expanding = lexical.clone().home_dirs(FakeHomeDirs(homes));
+
+        assert_eq!(Path::new("/home/me"), expanding.resolve("~").unwrap());
+        assert_eq!(
+            Path::new("/home/me/a"),
+            expanding.resolve("~/x/../a").unwrap()
+        );
+        assert_eq!(
+            Path::new("/home/bob/a"),
+            expanding.resolve("~bob/a").unwrap()
+        );
+        assert_eq!(Path::new("/work/a/~"), expanding.resolve("a/~").unwrap());
+        assert_eq!(Path::new("/work/~"), lexical.resolve("~").unwrap());
+
+        match expanding.resolve("~nobody/a") {
+            Err(Error::NoHomeDir { user, path }) => {
+                assert_eq!(Some(OsString::from("nobody")), user);
+                assert_eq!(Path::new("~nobody/a"), path);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        assert_eq!(
+            None,
+            SystemHomeDirs.user_home_dir(OsStr::new("no such user"))
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! async versions of the resolution functions, enabled by the `tokio` feature.
+//!
+//! Each function runs its sync counterpart on tokio's blocking thread pool
+//! (`tokio::task::spawn_blocking`), so the results are exactly the same.
+
+use crate::{AbsolutePathBuf, Absolutizer, CanonicalPathBuf, Error, Operation, Result};
+use std::io;
+use std::panic;
+use std::path::{Path, PathBuf};
+use tokio::task;
+
+/// run `f` on the blocking thread pool. A panic in `f` is propagated.
+async fn spawn_blocking<T, F>(path: PathBuf, f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(PathBuf) -> Result<T> + Send + 'static,
+{
+    let background = path.clone();
+    match task::spawn_blocking(move || f(background)).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+        // the task was cancelled; tokio turns that into an `io::Error`.
+        Err(e) => Err(Error::io(Operation::Canonicalize, path, io::Error::from(e))),
+    }
+}
+
+/// async version of `crate::to_absolute`.
+pub async fn to_absolute(
+    current: impl AsRef<Path>,
+    relative: impl AsRef<Path>,
+) -> Result<AbsolutePathBuf> {
+    let current = current.as_ref().to_path_buf();
+    spawn_blocking(relative.as_ref().to_path_buf(), move |relative| {
+        crate::to_absolute(current, relative)
+    })
+    .await
+}
+
+/// async version of `crate::to_absolute_from_current_dir`.
+pub async fn to_absolute_from_current_dir(relative: impl AsRef<Path>) -> Result<AbsolutePathBuf> {
+    spawn_blocking(
+        relative.as_ref().to_path_buf(),
+        crate::to_absolute_from_current_dir,
+    )
+    .await
+}
+
+/// async version of `crate::to_absolute_partial`.
+pub async fn to_absolute_partial(
+    current: impl AsRef<Path>,
+    relative: impl AsRef<Path>,
+) -> Result<AbsolutePathBuf> {
+    let current = current.as_ref().to_path_buf();
+    spawn_blocking(relative.as_ref().to_path_buf(), move |relative| {
+        crate::to_absolute_partial(current, relative)
+    })
+    .await
+}
+
+/// async version of `crate::canonicalize`.
+pub async fn canonicalize(path: impl AsRef<Path>) -> Result<CanonicalPathBuf> {
+    spawn_blocking(path.as_ref().to_path_buf(), crate::canonicalize).await
+}
+
+/// async version of `crate::canonicalize_partial`.
+pub async fn canonicalize_partial(path: impl AsRef<Path>) -> Result<AbsolutePathBuf> {
+    spawn_blocking(path.as_ref().to_path_buf(), crate::canonicalize_partial).await
+}
+
+/// async version of `Absolutizer::resolve`.
+pub async fn resolve(absolutizer: &Absolutizer, path: impl AsRef<Path>) -> Result<AbsolutePathBuf> {
+    let absolutizer = absolutizer.clone();
+    spawn_blocking(path.as_ref().to_path_buf(), move |path| {
+        absolutizer.resolve(path)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn test_parity() {
+        let current_dir = env::current_dir().unwrap();
+
+        assert_eq!(
+            crate::canonicalize(&current_dir).unwrap(),
+            super::canonicalize(&current_dir).await.unwrap()
+        );
+        assert_eq!(
+            crate::to_absolute(&current_dir, "src").unwrap(),
+            super::to_absolute(&current_dir, "src").await.unwrap()
+        );
+        assert_eq!(
+            crate::to_absolute_from_current_dir("src").unwrap(),
+            super::to_absolute_from_current_dir("src").await.unwrap()
+        );
+        assert_eq!(
+            crate::canonicalize_partial("src/new.rs").unwrap(),
+            super::canonicalize_partial("src/new.rs").await.unwrap()
+        );
+
+        let missing = Path::new("missing/file");
+        assert_eq!(
+            crate::to_absolute(&current_dir, missing)
+                .unwrap_err()
+                .to_string(),
+            super::to_absolute(&current_dir, missing)
+                .await
+                .unwrap_err()
+                .to_string()
+        );
+    }
+}
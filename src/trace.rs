@@ -0,0 +1,108 @@
+use crate::{AbsolutePath, AbsolutePathBuf, Absolutizer, Result};
+use std::path::{Path, PathBuf};
+
+/// one step of the component walk.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TraceStep {
+    /// the path was visited and isn't a symlink.
+    Visit { path: PathBuf },
+    /// the symlink was read. A relative target is relative to the directory
+    /// containing the link.
+    Symlink { link: PathBuf, target: PathBuf },
+    /// `..` went from `from` to `to`. `across_link` is true if `from` was
+    /// reached through a symlink, so `to` isn't the parent of the path as
+    /// written.
+    Parent {
+        from: PathBuf,
+        to: PathBuf,
+        across_link: bool,
+    },
+}
+
+/// the result of `Absolutizer::resolve_traced`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Trace {
+    resolved: AbsolutePathBuf,
+    steps: Vec<TraceStep>,
+}
+
+impl Trace {
+    pub fn resolved(&self) -> &AbsolutePath {
+        &self.resolved
+    }
+
+    /// the steps in the order they were taken.
+    pub fn steps(&self) -> &[TraceStep] {
+        &self.steps
+    }
+
+    pub fn into_parts(self) -> (AbsolutePathBuf, Vec<TraceStep>) {
+        (self.resolved, self.steps)
+    }
+}
+
+impl Absolutizer {
+    /// canonicalize the path with this configuration, recording every step of
+    /// the resolution. Symlinks are always followed and the file must exist,
+    /// like `canonicalize`.
+    pub fn resolve_traced(&self, path: impl AsRef<Path>) -> Result<Trace> {
+        let joined = self.joined(path.as_ref())?;
+
+        let mut steps = Vec::new();
+        let resolved = self.walk().traced(&mut steps).canonicalize(&joined)?;
+        let resolved = self.finish(resolved)?;
+        Ok(Trace { resolved, steps })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TraceStep;
+    use crate::testing::FakeFs;
+    use crate::Absolutizer;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_resolve_traced() {
+        let fs = FakeFs::new()
+            .dir("/x/y")
+            .dir("/x/z")
+            .symlink("/x/y", "/a/link")
+            .with_current_dir("/a");
+        let absolutizer = Absolutizer::new().fs(fs);
+        let visit = |path: &str| TraceStep::Visit {
+            path: PathBuf::from(path),
+        };
+        let parent = |from: &str, to: &str, across_link| TraceStep::Parent {
+            from: PathBuf::from(from),
+            to: PathBuf::from(to),
+            across_link,
+        };
+
+        let trace = absolutizer.resolve_traced("link/../z").unwrap();
+        assert_eq!(Path::new("/x/z"), trace.resolved());
+        assert_eq!(
+            &[
+                visit("/a"),
+                TraceStep::Symlink {
+                    link: PathBuf::from("/a/link"),
+                    target: PathBuf::from("/x/y"),
+                },
+                visit("/x"),
+                visit("/x/y"),
+                parent("/x/y", "/x", true),
+                visit("/x/z"),
+            ][..],
+            trace.steps()
+        );
+
+        let (resolved, steps) = absolutizer.resolve_traced("/a/../a").unwrap().into_parts();
+        assert_eq!(Path::new("/a"), resolved);
+        assert_eq!(
+            vec![visit("/a"), parent("/a", "/", false), visit("/a")],
+            steps
+        );
+
+        assert!(absolutizer.resolve_traced("missing").is_err());
+    }
+}
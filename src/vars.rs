@@ -0,0 +1,260 @@
+use crate::{Error, Result};
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str;
+use std::sync::Arc;
+
+/// the syntax of variable references.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VarSyntax {
+    /// `$VAR` and `${VAR}`, where `VAR` is letters, digits and `_` not
+    /// starting with a digit.
+    Posix,
+    /// `%VAR%`.
+    Windows,
+}
+
+/// the values of variables.
+pub trait VarSource {
+    fn var(&self, name: &str) -> Option<OsString>;
+}
+
+/// the environment variables of the process.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessEnv;
+
+impl VarSource for ProcessEnv {
+    fn var(&self, name: &str) -> Option<OsString> {
+        env::var_os(name)
+    }
+}
+
+impl<V: AsRef<OsStr>> VarSource for HashMap<String, V> {
+    fn var(&self, name: &str) -> Option<OsString> {
+        self.get(name).map(|value| value.as_ref().to_os_string())
+    }
+}
+
+impl<F: Fn(&str) -> Option<OsString>> VarSource for F {
+    fn var(&self, name: &str) -> Option<OsString> {
+        self(name)
+    }
+}
+
+/// replace the variable references in the path with their values. Text which
+/// isn't a complete reference (e.g. a lone `$` or an unclosed `${`) is kept as
+/// is. On Unix the path may be any bytes; elsewhere a path which isn't valid
+/// Unicode is an error.
+pub fn expand_vars(
+    path: impl AsRef<Path>,
+    syntax: VarSyntax,
+    source: &dyn VarSource,
+) -> Result<PathBuf> {
+    let path = path.as_ref();
+
+    let mut expanded = OsString::new();
+    let mut rest = path_bytes(path)?;
+    while let Some((before, name, after)) = next_reference(rest, syntax) {
+        let value = source.var(name).ok_or_else(|| Error::UndefinedVariable {
+            name: name.to_string(),
+            path: path.to_path_buf(),
+        })?;
+        expanded.push(os_str(before));
+        expanded.push(value);
+        rest = after;
+    }
+    expanded.push(os_str(rest));
+
+    Ok(PathBuf::from(expanded))
+}
+
+/// the bytes of the path. The references are ASCII, so they're found the
+/// same in any bytes which keep ASCII as is.
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Result<&[u8]> {
+    use std::os::unix::ffi::OsStrExt;
+
+    Ok(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Result<&[u8]> {
+    path.to_str()
+        .map(str::as_bytes)
+        .ok_or_else(|| Error::NotUnicode {
+            path: path.to_path_buf(),
+        })
+}
+
+#[cfg(unix)]
+fn os_str(bytes: &[u8]) -> &OsStr {
+    use std::os::unix::ffi::OsStrExt;
+
+    OsStr::from_bytes(bytes)
+}
+
+#[cfg(not(unix))]
+fn os_str(bytes: &[u8]) -> &OsStr {
+    // the bytes of a `str` split at ASCII characters are still UTF-8.
+    OsStr::new(str::from_utf8(bytes).expect("split at ASCII characters"))
+}
+
+/// find the first reference, and split the text to the text before it, the
+/// variable name and the text after it.
+fn next_reference(text: &[u8], syntax: VarSyntax) -> Option<(&[u8], &str, &[u8])> {
+    let sigil = match syntax {
+        VarSyntax::Posix => b'$',
+        VarSyntax::Windows => b'%',
+    };
+
+    let mut offset = 0;
+    while let Some(found) = find(&text[offset..], sigil) {
+        let start = offset + found;
+        let after_sigil = &text[start + 1..];
+        let reference = match syntax {
+            VarSyntax::Posix if after_sigil.first() == Some(&b'{') => find(&after_sigil[1..], b'}')
+                .map(|end| (&after_sigil[1..end + 1], &after_sigil[end + 2..])),
+            VarSyntax::Posix => {
+                let end = after_sigil
+                    .iter()
+                    .position(|&b| !(b.is_ascii_alphanumeric() || b == b'_'))
+                    .unwrap_or(after_sigil.len());
+                Some((&after_sigil[..end], &after_sigil[end..]))
+            }
+            VarSyntax::Windows => {
+                find(after_sigil, b'%').map(|end| (&after_sigil[..end], &after_sigil[end + 1..]))
+            }
+        };
+        let reference = reference
+            .and_then(|(name, after)| Some((str::from_utf8(name).ok()?, after)))
+            .filter(|&(name, _)| match syntax {
+                VarSyntax::Posix => is_posix_name(name),
+                VarSyntax::Windows => !name.is_empty(),
+            });
+        match reference {
+            Some((name, after)) => return Some((&text[..start], name, after)),
+            None => offset = start + 1,
+        }
+    }
+
+    None
+}
+
+fn find(text: &[u8], byte: u8) -> Option<usize> {
+    text.iter().position(|&b| b == byte)
+}
+
+fn is_posix_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// the expansion configured in `Absolutizer`.
+#[derive(Clone)]
+pub(crate) struct VarExpansion {
+    pub(crate) syntax: VarSyntax,
+    pub(crate) source: Arc<dyn VarSource + Send + Sync>,
+}
+
+impl fmt::Debug for VarExpansion {
+    fn fmt(&self, b: &mut fmt::Formatter) -> fmt::Result {
+        b.debug_struct("VarExpansion")
+            .field("syntax", &self.syntax)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand_vars, ProcessEnv, VarSyntax};
+    use crate::{Absolutizer, Error};
+    use std::collections::HashMap;
+    use std::ffi::OsString;
+    use std::path::Path;
+
+    #[test]
+    fn test_expand_vars() {
+        let mut vars = HashMap::new();
+        vars.insert("OUT_DIR".to_string(), "/build/out");
+        vars.insert("LOCALAPPDATA".to_string(), "/appdata");
+        let posix = |path: &str| expand_vars(path, VarSyntax::Posix, &vars);
+        let windows = |path: &str| expand_vars(path, VarSyntax::Windows, &vars);
+
+        assert_eq!(
+            Path::new("/build/out/gen"),
+            posix("${OUT_DIR}/gen").unwrap()
+        );
+        assert_eq!(Path::new("/build/out/gen"), posix("$OUT_DIR/gen").unwrap());
+        assert_eq!(
+            Path::new("/appdata/tool"),
+            windows("%LOCALAPPDATA%/tool").unwrap()
+        );
+        assert_eq!(
+            Path::new("$/a/${/%OUT_DIR"),
+            posix("$/a/${/%OUT_DIR").unwrap()
+        );
+        assert_eq!(
+            Path::new("$OUT_DIR/100%"),
+            windows("$OUT_DIR/100%").unwrap()
+        );
+        match posix("${OUT_DIR}/$MISSING/x") {
+            Err(Error::UndefinedVariable { name, path }) => {
+                assert_eq!("MISSING", name);
+                assert_eq!(Path::new("${OUT_DIR}/$MISSING/x"), path);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let closure = |name: &str| Some(OsString::from(format!("/{}", name.to_lowercase())));
+        assert_eq!(
+            Path::new("/a/b"),
+            expand_vars("$A$B", VarSyntax::Posix, &closure).unwrap()
+        );
+
+        // cargo sets this for the test process, so the environment doesn't
+        // have to be changed while other tests read it.
+        let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let absolutizer = Absolutizer::new()
+            .follow_symlinks(false)
+            .must_exist(false)
+            .base("/work")
+            .expand_vars(VarSyntax::Posix, ProcessEnv);
+        assert_eq!(
+            manifest_dir.join("gen"),
+            absolutizer.resolve("$CARGO_MANIFEST_DIR/x/../gen").unwrap()
+        );
+        assert_eq!(
+            Path::new("/work/$/gen"),
+            absolutizer.resolve("$/gen").unwrap()
+        );
+        assert!(absolutizer.resolve("$TO_ABSOLUTE_UNDEFINED").is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_non_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut vars = HashMap::new();
+        vars.insert("OUT_DIR".to_string(), "/build/out");
+        let path = |bytes: &[u8]| Path::new(OsStr::from_bytes(bytes)).to_path_buf();
+
+        assert_eq!(
+            path(b"/build/out/\xff"),
+            expand_vars(path(b"$OUT_DIR/\xff"), VarSyntax::Posix, &vars).unwrap()
+        );
+        match expand_vars(path(b"$UNDEFINED_XYZ/\xff"), VarSyntax::Posix, &vars) {
+            Err(Error::UndefinedVariable { name, .. }) => assert_eq!("UNDEFINED_XYZ", name),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
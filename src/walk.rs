@@ -0,0 +1,247 @@
+use crate::{Error, FileKind, Fs, Operation, Result, TraceStep};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// the same limit as Linux's `MAXSYMLINKS`.
+pub(crate) const MAX_SYMLINK_EXPANSIONS: usize = 40;
+
+/// the limits of the walk against hostile trees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Limits {
+    pub(crate) max_symlinks: usize,
+    pub(crate) max_depth: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_symlinks: MAX_SYMLINK_EXPANSIONS,
+            max_depth: None,
+        }
+    }
+}
+
+/// resolved paths keyed by unresolved paths, shared by walks. It's only
+/// locked to look up or insert an entry, never during a syscall.
+pub(crate) type WalkCache = Mutex<HashMap<PathBuf, CacheEntry>>;
+
+/// a remembered resolution.
+#[derive(Debug, Clone)]
+pub(crate) struct CacheEntry {
+    pub(crate) resolved: PathBuf,
+    /// the paths visited after the first symlink, which may be anywhere in
+    /// the tree. The other paths the entry depends on are in the subtrees of
+    /// the unresolved and resolved paths.
+    pub(crate) through: Vec<PathBuf>,
+}
+
+impl CacheEntry {
+    /// whether changing the subtree `path` may change the entry, apart from
+    /// its unresolved path.
+    pub(crate) fn depends_on(&self, path: &Path) -> bool {
+        self.resolved.starts_with(path) || self.through.iter().any(|p| p.starts_with(path))
+    }
+}
+
+pub(crate) fn lock_cache(cache: &WalkCache) -> MutexGuard<'_, HashMap<PathBuf, CacheEntry>> {
+    // the cache is always consistent even if a thread panicked.
+    cache.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// resolves symlinks component by component, like `realpath(3)`.
+pub(crate) struct Walk<'a, F: ?Sized> {
+    fs: &'a F,
+    cache: Option<&'a WalkCache>,
+    trace: Option<&'a mut Vec<TraceStep>>,
+    limits: Limits,
+    expansions: usize,
+    /// the symlinks being expanded, outermost first.
+    chain: Vec<PathBuf>,
+    /// whether each component of the resolved path came from a symlink
+    /// target. Only kept up to date without the cache.
+    from_link: Vec<bool>,
+    /// the paths visited after the first symlink, recorded with the cache.
+    through: Vec<PathBuf>,
+}
+
+impl<'a, F: Fs + ?Sized> Walk<'a, F> {
+    pub(crate) fn new(fs: &'a F) -> Walk<'a, F> {
+        Walk {
+            fs,
+            cache: None,
+            trace: None,
+            limits: Limits::default(),
+            expansions: 0,
+            chain: Vec::new(),
+            from_link: Vec::new(),
+            through: Vec::new(),
+        }
+    }
+
+    pub(crate) fn with_cache(fs: &'a F, cache: &'a WalkCache) -> Walk<'a, F> {
+        Walk {
+            cache: Some(cache),
+            ..Walk::new(fs)
+        }
+    }
+
+    pub(crate) fn limits(mut self, limits: Limits) -> Walk<'a, F> {
+        self.limits = limits;
+        self
+    }
+
+    /// record every step to `trace`.
+    pub(crate) fn traced(mut self, trace: &'a mut Vec<TraceStep>) -> Walk<'a, F> {
+        self.trace = Some(trace);
+        self
+    }
+
+    /// canonicalize the absolute path. Every prefix of the path is remembered
+    /// in the cache, if any.
+    pub(crate) fn canonicalize(&mut self, path: &Path) -> Result<PathBuf> {
+        let (mut resolved, mut unresolved, rest) = match self.longest_cached(path) {
+            Some((ancestor, entry)) => {
+                let rest = path
+                    .strip_prefix(ancestor)
+                    .unwrap_or_else(|_| Path::new(""));
+                self.through = entry.through;
+                (entry.resolved, ancestor.to_path_buf(), rest)
+            }
+            None => (PathBuf::new(), PathBuf::new(), path),
+        };
+
+        for component in rest.components() {
+            self.step(&mut resolved, component)?;
+            unresolved.push(component);
+            self.remember(&unresolved, &resolved);
+        }
+
+        Ok(resolved)
+    }
+
+    fn longest_cached<'p>(&self, path: &'p Path) -> Option<(&'p Path, CacheEntry)> {
+        let cache = lock_cache(self.cache?);
+        path.ancestors()
+            .find_map(|ancestor| cache.get(ancestor).map(|entry| (ancestor, entry.clone())))
+    }
+
+    fn record(&mut self, step: TraceStep) {
+        if let Some(ref mut trace) = self.trace {
+            trace.push(step);
+        }
+    }
+
+    fn cached(&self, unresolved: &Path) -> Option<CacheEntry> {
+        lock_cache(self.cache?).get(unresolved).cloned()
+    }
+
+    fn remember(&mut self, unresolved: &Path, resolved: &Path) {
+        if let Some(cache) = self.cache {
+            let entry = CacheEntry {
+                resolved: resolved.to_path_buf(),
+                through: self.through.clone(),
+            };
+            lock_cache(cache).insert(unresolved.to_path_buf(), entry);
+        }
+    }
+
+    /// record a visited path the entries remembered from now on depend on.
+    fn visit(&mut self, path: &Path, is_symlink: bool) {
+        if self.cache.is_some() && (is_symlink || !self.through.is_empty()) {
+            self.through.push(path.to_path_buf());
+        }
+    }
+
+    /// apply one component to the resolved path.
+    fn step(&mut self, resolved: &mut PathBuf, component: Component) -> Result<()> {
+        let name = match component {
+            Component::Prefix(_) | Component::RootDir => {
+                resolved.push(component);
+                self.from_link.clear();
+                return Ok(());
+            }
+            Component::CurDir => return Ok(()),
+            Component::ParentDir => {
+                let from = resolved.clone();
+                resolved.pop();
+                let across_link = self.from_link.pop().unwrap_or(false);
+                self.record(TraceStep::Parent {
+                    from,
+                    to: resolved.clone(),
+                    across_link,
+                });
+                return Ok(());
+            }
+            Component::Normal(name) => name,
+        };
+
+        let candidate = resolved.join(name);
+        if let Some(hit) = self.cached(&candidate) {
+            for path in hit.through {
+                if !self.through.contains(&path) {
+                    self.through.push(path);
+                }
+            }
+            *resolved = hit.resolved;
+            return Ok(());
+        }
+
+        let kind = self
+            .fs
+            .symlink_metadata(&candidate)
+            .map_err(|e| Error::io(Operation::Metadata, &candidate, e))?;
+        self.visit(&candidate, kind == FileKind::Symlink);
+        if kind != FileKind::Symlink {
+            if let Some(max) = self.limits.max_depth {
+                if candidate.components().count() > max {
+                    return Err(Error::TooDeep {
+                        path: candidate,
+                        max,
+                    });
+                }
+            }
+            self.from_link.push(!self.chain.is_empty());
+            self.record(TraceStep::Visit {
+                path: candidate.clone(),
+            });
+            *resolved = candidate;
+            return Ok(());
+        }
+
+        // a symlink is only reached again while it's being expanded if the
+        // expansion never ends.
+        if let Some(start) = self.chain.iter().position(|link| *link == candidate) {
+            return Err(Error::SymlinkLoop {
+                path: candidate,
+                members: self.chain[start..].to_vec(),
+            });
+        }
+        self.expansions += 1;
+        if self.expansions > self.limits.max_symlinks {
+            return Err(Error::TooManySymlinks {
+                path: candidate,
+                max: self.limits.max_symlinks,
+            });
+        }
+        let target = self
+            .fs
+            .read_link(&candidate)
+            .map_err(|e| Error::io(Operation::ReadLink, &candidate, e))?;
+
+        self.record(TraceStep::Symlink {
+            link: candidate.clone(),
+            target: target.clone(),
+        });
+
+        // the relative target is relative to the directory containing the link.
+        self.chain.push(candidate.clone());
+        for component in target.components() {
+            self.step(resolved, component)?;
+        }
+        self.chain.pop();
+        self.remember(&candidate, resolved);
+
+        Ok(())
+    }
+}
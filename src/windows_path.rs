@@ -0,0 +1,350 @@
+use crate::{Error, Result};
+use std::fmt;
+use std::path::PathBuf;
+
+/// the prefix of a Windows path. Unlike `std::path::Prefix`, this can be parsed
+/// from a plain string on any platform.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum WindowsPrefix {
+    /// `C:`
+    Disk(char),
+    /// `\\?\C:`
+    VerbatimDisk(char),
+    /// `\\server\share`
+    UNC { server: String, share: String },
+    /// `\\?\UNC\server\share`
+    VerbatimUNC { server: String, share: String },
+    /// `\\.\COM1`
+    DeviceNS(String),
+    /// `\\?\GLOBALROOT`
+    GlobalRoot,
+    /// `\\?\pictures`, or any other verbatim prefix.
+    Verbatim(String),
+}
+
+impl WindowsPrefix {
+    /// parse the prefix at the start of the path. Returns the prefix and the
+    /// rest of the path.
+    pub fn parse(path: &str) -> Option<(WindowsPrefix, &str)> {
+        if let Some(rest) = path.strip_prefix(r"\\?\") {
+            // verbatim paths only accept `\` as a separator.
+            let (first, after_first) = split_component(rest, |c| c == '\\');
+            if first.eq_ignore_ascii_case("UNC") {
+                let (server, rest) = split_component(skip_separator(after_first), |c| c == '\\');
+                let (share, rest) = split_component(skip_separator(rest), |c| c == '\\');
+                let prefix = WindowsPrefix::VerbatimUNC {
+                    server: server.to_string(),
+                    share: share.to_string(),
+                };
+                return Some((prefix, rest));
+            }
+            if let Some(disk) = parse_disk(first) {
+                return Some((WindowsPrefix::VerbatimDisk(disk), after_first));
+            }
+            if first.eq_ignore_ascii_case("GLOBALROOT") {
+                return Some((WindowsPrefix::GlobalRoot, after_first));
+            }
+            return Some((WindowsPrefix::Verbatim(first.to_string()), after_first));
+        }
+
+        let mut chars = path.chars();
+        match (chars.next(), chars.next()) {
+            (Some(a), Some(b)) if is_separator(a) && is_separator(b) => {
+                let rest = &path[2..];
+                if let Some(device) = rest.strip_prefix('.') {
+                    if device.starts_with(is_separator) {
+                        let (device, rest) = split_component(&device[1..], is_separator);
+                        return Some((WindowsPrefix::DeviceNS(device.to_string()), rest));
+                    }
+                }
+                let (server, rest) = split_component(rest, is_separator);
+                let (share, rest) = split_component(skip_separator(rest), is_separator);
+                let prefix = WindowsPrefix::UNC {
+                    server: server.to_string(),
+                    share: share.to_string(),
+                };
+                Some((prefix, rest))
+            }
+            _ => {
+                let disk = parse_disk(path.get(..2)?)?;
+                Some((WindowsPrefix::Disk(disk), &path[2..]))
+            }
+        }
+    }
+
+    /// get the plain (non-verbatim) form of this prefix, or `None` if there is
+    /// no such form.
+    pub fn plain(&self) -> Option<WindowsPrefix> {
+        match *self {
+            WindowsPrefix::Disk(disk) | WindowsPrefix::VerbatimDisk(disk) => {
+                Some(WindowsPrefix::Disk(disk))
+            }
+            WindowsPrefix::UNC {
+                ref server,
+                ref share,
+            }
+            | WindowsPrefix::VerbatimUNC {
+                ref server,
+                ref share,
+            } => Some(WindowsPrefix::UNC {
+                server: server.clone(),
+                share: share.clone(),
+            }),
+            WindowsPrefix::DeviceNS(_) | WindowsPrefix::GlobalRoot | WindowsPrefix::Verbatim(_) => {
+                None
+            }
+        }
+    }
+
+    pub fn kind(&self) -> PrefixKind {
+        match *self {
+            WindowsPrefix::Disk(_) => PrefixKind::Disk,
+            WindowsPrefix::VerbatimDisk(_) => PrefixKind::VerbatimDisk,
+            WindowsPrefix::UNC { .. } => PrefixKind::UNC,
+            WindowsPrefix::VerbatimUNC { .. } => PrefixKind::VerbatimUNC,
+            WindowsPrefix::DeviceNS(_) => PrefixKind::DeviceNS,
+            WindowsPrefix::GlobalRoot => PrefixKind::GlobalRoot,
+            WindowsPrefix::Verbatim(_) => PrefixKind::Verbatim,
+        }
+    }
+
+    pub fn is_verbatim(&self) -> bool {
+        match *self {
+            WindowsPrefix::VerbatimDisk(_)
+            | WindowsPrefix::VerbatimUNC { .. }
+            | WindowsPrefix::GlobalRoot
+            | WindowsPrefix::Verbatim(_) => true,
+            WindowsPrefix::Disk(_) | WindowsPrefix::UNC { .. } | WindowsPrefix::DeviceNS(_) => {
+                false
+            }
+        }
+    }
+}
+
+impl fmt::Display for WindowsPrefix {
+    fn fmt(&self, b: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WindowsPrefix::Disk(disk) => write!(b, "{}:", disk),
+            WindowsPrefix::VerbatimDisk(disk) => write!(b, r"\\?\{}:", disk),
+            WindowsPrefix::UNC {
+                ref server,
+                ref share,
+            } => write!(b, r"\\{}\{}", server, share),
+            WindowsPrefix::VerbatimUNC {
+                ref server,
+                ref share,
+            } => write!(b, r"\\?\UNC\{}\{}", server, share),
+            WindowsPrefix::DeviceNS(ref device) => write!(b, r"\\.\{}", device),
+            WindowsPrefix::GlobalRoot => write!(b, r"\\?\GLOBALROOT"),
+            WindowsPrefix::Verbatim(ref name) => write!(b, r"\\?\{}", name),
+        }
+    }
+}
+
+/// the kind of `WindowsPrefix`, without its contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PrefixKind {
+    Disk,
+    VerbatimDisk,
+    UNC,
+    VerbatimUNC,
+    DeviceNS,
+    GlobalRoot,
+    Verbatim,
+}
+
+impl fmt::Display for PrefixKind {
+    fn fmt(&self, b: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match *self {
+            PrefixKind::Disk => "disk",
+            PrefixKind::VerbatimDisk => "verbatim disk",
+            PrefixKind::UNC => "UNC",
+            PrefixKind::VerbatimUNC => "verbatim UNC",
+            PrefixKind::DeviceNS => "device namespace",
+            PrefixKind::GlobalRoot => "global root",
+            PrefixKind::Verbatim => "verbatim",
+        };
+        write!(b, "{}", kind)
+    }
+}
+
+/// a Windows path parsed from a plain string, so that Windows paths can be
+/// handled on any platform.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WindowsPath {
+    prefix: Option<WindowsPrefix>,
+    rest: String,
+}
+
+impl WindowsPath {
+    pub fn parse(path: &str) -> WindowsPath {
+        match WindowsPrefix::parse(path) {
+            Some((prefix, rest)) => WindowsPath {
+                prefix: Some(prefix),
+                rest: rest.to_string(),
+            },
+            None => WindowsPath {
+                prefix: None,
+                rest: path.to_string(),
+            },
+        }
+    }
+
+    pub fn prefix(&self) -> Option<&WindowsPrefix> {
+        self.prefix.as_ref()
+    }
+
+    /// the path after the prefix, e.g. `\Windows` for `C:\Windows`.
+    pub fn rest(&self) -> &str {
+        &self.rest
+    }
+
+    pub fn has_root(&self) -> bool {
+        self.rest.starts_with(is_separator)
+    }
+
+    /// whether the path is absolute, in the same sense as
+    /// `std::path::Path::is_absolute` on Windows.
+    pub fn is_absolute(&self) -> bool {
+        match self.prefix {
+            Some(ref prefix) if prefix.is_verbatim() => true,
+            Some(WindowsPrefix::UNC { .. }) | Some(WindowsPrefix::DeviceNS(_)) => true,
+            Some(WindowsPrefix::Disk(_)) => self.has_root(),
+            _ => false,
+        }
+    }
+
+    /// get the path with its prefix replaced by the plain form, e.g. `C:\foo`
+    /// for `\\?\C:\foo`.
+    pub fn to_plain(&self) -> Result<WindowsPath> {
+        let prefix = match self.prefix {
+            Some(ref prefix) => Some(prefix.plain().ok_or_else(|| Error::UnsupportedPrefix {
+                path: PathBuf::from(self.to_string()),
+                prefix: prefix.kind(),
+            })?),
+            None => None,
+        };
+
+        Ok(WindowsPath {
+            prefix,
+            rest: self.rest.clone(),
+        })
+    }
+}
+
+impl fmt::Display for WindowsPath {
+    fn fmt(&self, b: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref prefix) = self.prefix {
+            write!(b, "{}", prefix)?;
+        }
+        write!(b, "{}", self.rest)
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    c == '\\' || c == '/'
+}
+
+fn skip_separator(path: &str) -> &str {
+    let mut chars = path.chars();
+    match chars.next() {
+        Some(c) if is_separator(c) => chars.as_str(),
+        _ => path,
+    }
+}
+
+/// split the first component and the rest (which starts with the separator).
+fn split_component(path: &str, is_separator: impl Fn(char) -> bool) -> (&str, &str) {
+    match path.find(is_separator) {
+        Some(index) => path.split_at(index),
+        None => (path, ""),
+    }
+}
+
+fn parse_disk(component: &str) -> Option<char> {
+    let mut chars = component.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(disk), Some(':'), None) if disk.is_ascii_alphabetic() => Some(disk),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{WindowsPath, WindowsPrefix};
+
+    fn prefix(path: &str) -> Option<WindowsPrefix> {
+        WindowsPath::parse(path).prefix().cloned()
+    }
+
+    fn unc(server: &str, share: &str) -> WindowsPrefix {
+        WindowsPrefix::UNC {
+            server: server.to_string(),
+            share: share.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Some(WindowsPrefix::Disk('C')), prefix(r#"C:\Windows"#));
+        assert_eq!(Some(WindowsPrefix::Disk('c')), prefix(r#"c:relative"#));
+        assert_eq!(
+            Some(WindowsPrefix::VerbatimDisk('C')),
+            prefix(r#"\\?\C:\Windows"#)
+        );
+        assert_eq!(
+            Some(unc("server", "share")),
+            prefix(r#"\\server\share\dir"#)
+        );
+        assert_eq!(
+            Some(unc("server", "share")),
+            prefix(r#"//server/share/dir"#)
+        );
+        assert_eq!(
+            Some(WindowsPrefix::VerbatimUNC {
+                server: "server".to_string(),
+                share: "share".to_string(),
+            }),
+            prefix(r#"\\?\UNC\server\share\dir"#)
+        );
+        assert_eq!(
+            Some(WindowsPrefix::DeviceNS("COM1".to_string())),
+            prefix(r#"\\.\COM1"#)
+        );
+        assert_eq!(
+            Some(WindowsPrefix::GlobalRoot),
+            prefix(r#"\\?\GLOBALROOT\Device\HarddiskVolume1"#)
+        );
+        assert_eq!(
+            Some(WindowsPrefix::Verbatim("pictures".to_string())),
+            prefix(r#"\\?\pictures\dir"#)
+        );
+        assert_eq!(None, prefix(r#"\Windows"#));
+        assert_eq!(None, prefix(r#"relative\path"#));
+
+        let path = WindowsPath::parse(r#"\\?\UNC\server\share\dir"#);
+        assert_eq!(r#"\dir"#, path.rest());
+        assert_eq!(r#"\\?\UNC\server\share\dir"#, path.to_string());
+        assert!(path.is_absolute());
+        assert!(!WindowsPath::parse(r#"C:relative"#).is_absolute());
+    }
+
+    #[test]
+    fn test_to_plain() {
+        let plain = |path: &str| WindowsPath::parse(path).to_plain().map(|p| p.to_string());
+
+        assert_eq!(r#"C:\Windows"#, plain(r#"\\?\C:\Windows"#).unwrap());
+        assert_eq!(r#"C:\Windows"#, plain(r#"C:\Windows"#).unwrap());
+        assert_eq!(
+            r#"\\server\share\dir"#,
+            plain(r#"\\?\UNC\server\share\dir"#).unwrap()
+        );
+        assert_eq!(
+            r#"\\server\share\dir"#,
+            plain(r#"\\server\share\dir"#).unwrap()
+        );
+        assert!(plain(r#"\\?\pictures\dir"#).is_err());
+        assert!(plain(r#"\\.\COM1"#).is_err());
+        assert!(plain(r#"\\?\GLOBALROOT\Device\HarddiskVolume1"#).is_err());
+    }
+}